@@ -1,16 +1,51 @@
+use crate::lexer::{Comment, FilePos};
 use serde::ser::SerializeMap;
 use serde::Serialize;
+use std::fmt;
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub enum Value {
-    Number(i32),
-    Float(f32),
+    #[default]
+    Null,
+    Bool(bool),
+    Number(i64),
+    Float(f64),
     String(String),
     ObjectWithCalls(Vec<RecordOrCall>),
     Object(Vec<Record>),
-    Array(Vec<Value>),
+    Array(Vec<Element>),
     Call(Call),
+    Reference(Reference),
     Typed(Typed),
+    /// Stands in for a value that failed to parse, see
+    /// [`crate::parser::parse_recovering`].
+    Error(FilePos),
+}
+
+impl Value {
+    /// The name of the value's type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool(_) => "boolean",
+            Self::Number(_) => "number",
+            Self::Float(_) => "float",
+            Self::String(_) => "string",
+            Self::ObjectWithCalls(_) | Self::Object(_) => "object",
+            Self::Array(_) => "array",
+            Self::Call(_) => "call",
+            Self::Reference(_) => "reference",
+            Self::Typed(t) => t.value.type_name(),
+            Self::Error(_) => "error",
+        }
+    }
+}
+
+impl Value {
+    /// Builds an array out of plain values.
+    pub fn array(values: impl IntoIterator<Item = Value>) -> Self {
+        Self::Array(values.into_iter().map(Element::from).collect())
+    }
 }
 
 impl Serialize for Value {
@@ -19,15 +54,21 @@ impl Serialize for Value {
         S: serde::Serializer,
     {
         match self {
-            Self::Number(n) => serializer.serialize_i32(*n),
-            Self::Float(f) => serializer.serialize_f32(*f),
+            Self::Null => serializer.serialize_unit(),
+            Self::Bool(b) => serializer.serialize_bool(*b),
+            Self::Number(n) => serializer.serialize_i64(*n),
+            Self::Float(f) => serializer.serialize_f64(*f),
             Self::String(s) => serializer.serialize_str(s),
             Self::ObjectWithCalls(v) => {
-                let mut map = serializer.serialize_map(Some(v.len()))?;
+                let mut map = serializer.serialize_map(None)?;
                 for record in v {
                     match record {
-                        RecordOrCall::Call(c) => map.serialize_entry(&c.function, &c.value)?,
+                        RecordOrCall::Call(c) => match c.single() {
+                            Some(value) => map.serialize_entry(&c.function, value)?,
+                            None => map.serialize_entry(&c.function, &c.args)?,
+                        },
                         RecordOrCall::Record(r) => map.serialize_entry(&r.id, &r.value)?,
+                        RecordOrCall::Comment(_) => (),
                     }
                 }
                 map.end()
@@ -39,11 +80,18 @@ impl Serialize for Value {
                 }
                 map.end()
             }
-            Self::Call(_) => {
-                unimplemented!("calls should be evaluated");
-            }
+            Self::Call(c) => Err(serde::ser::Error::custom(format!(
+                "call to @{} was not evaluated",
+                c.function
+            ))),
+            Self::Reference(r) => Err(serde::ser::Error::custom(format!(
+                "reference {r} was not resolved"
+            ))),
+            Self::Error(pos) => Err(serde::ser::Error::custom(format!(
+                "value at {pos} failed to parse"
+            ))),
             Self::Typed(t) => t.serialize(serializer),
-            Self::Array(a) => serializer.collect_seq(a.iter()),
+            Self::Array(a) => serializer.collect_seq(a.iter().map(|e| &e.value)),
         }
     }
 }
@@ -63,16 +111,159 @@ impl Serialize for Typed {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+/// An element of an array.
+#[derive(Debug, Clone, Default)]
+pub struct Element {
+    pub value: Value,
+    /// Where the element starts, left empty for elements built in code.
+    pub pos: FilePos,
+    /// Comments on the lines before the element.
+    pub leading: Vec<Comment>,
+    /// Comments after the element, up to the next one.
+    pub trailing: Vec<Comment>,
+}
+
+impl From<Value> for Element {
+    fn from(value: Value) -> Self {
+        Self {
+            value,
+            ..Default::default()
+        }
+    }
+}
+
+/// Elements compare by value, positions and comments are layout.
+impl PartialEq for Element {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+/// An argument of a call, `name = value` when it has a name.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Argument {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub value: Value,
+    /// Where the argument starts, at its name for a named one. Left empty
+    /// for arguments built in code.
+    #[serde(skip)]
+    pub pos: FilePos,
+    /// Comments on the lines before the argument.
+    #[serde(skip)]
+    pub leading: Vec<Comment>,
+    /// Comments after the argument, up to the next one.
+    #[serde(skip)]
+    pub trailing: Vec<Comment>,
+}
+
+impl From<Value> for Argument {
+    fn from(value: Value) -> Self {
+        Self {
+            name: None,
+            value,
+            ..Default::default()
+        }
+    }
+}
+
+/// Arguments compare by name and value, positions and comments are layout.
+impl PartialEq for Argument {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.value == other.value
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Call {
     pub function: String,
-    pub value: Box<Value>,
+    /// Positional arguments come before named ones.
+    pub args: Vec<Argument>,
+    /// Where the sigil and function name were written, left empty for calls
+    /// built in code.
+    #[serde(skip)]
+    pub pos: FilePos,
 }
 
-#[derive(Debug, PartialEq, Clone, Serialize)]
+impl Call {
+    /// The arguments passed without a name, in order.
+    pub fn positional(&self) -> impl Iterator<Item = &Value> {
+        self.args
+            .iter()
+            .filter(|arg| arg.name.is_none())
+            .map(|arg| &arg.value)
+    }
+
+    /// The argument passed as `name = value`.
+    pub fn named(&self, name: &str) -> Option<&Value> {
+        self.args
+            .iter()
+            .find(|arg| arg.name.as_deref() == Some(name))
+            .map(|arg| &arg.value)
+    }
+
+    /// The argument of a call written as `@f value`, that is with exactly
+    /// one positional argument.
+    pub fn single(&self) -> Option<&Value> {
+        match self.args.as_slice() {
+            [Argument {
+                name: None, value, ..
+            }] => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Call {
+    fn eq(&self, other: &Self) -> bool {
+        self.function == other.function && self.args == other.args
+    }
+}
+
+/// `@ref server.port`, the evaluated value at a path of keys from the root
+/// of the document.
+#[derive(Debug, Clone, Default)]
+pub struct Reference {
+    pub path: Vec<String>,
+    /// Where the reference was written, from `@` to the end of the path.
+    pub pos: FilePos,
+}
+
+impl PartialEq for Reference {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@ref {}", self.path.join("."))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Record {
     pub id: String,
     pub value: Value,
+    /// Where the key was written, left empty for records built in code.
+    #[serde(skip)]
+    pub pos: FilePos,
+    /// The whole key the record was written with, `["server", "port"]` for
+    /// `port` in `server.port = 80`. Left empty for records built in code.
+    #[serde(skip)]
+    pub key: Vec<String>,
+    /// Whether the record was not written itself but stands for the prefix
+    /// of dotted keys, like `server` in `server.port = 80`.
+    #[serde(skip)]
+    pub implicit: bool,
+}
+
+/// Records compare by content, so documents that only differ in layout,
+/// including how their keys were written, are equal.
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.value == other.value
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize)]
@@ -80,6 +271,9 @@ pub struct Record {
 pub enum RecordOrCall {
     Record(Record),
     Call(Call),
+    /// Kept by the parser for tooling, ignored by evaluation.
+    #[serde(skip)]
+    Comment(Comment),
 }
 
 impl From<Record> for RecordOrCall {
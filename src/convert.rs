@@ -0,0 +1,350 @@
+use crate::ast::{Record, Typed, Value};
+use crate::eval::Error;
+use std::collections::HashMap;
+
+/// Reads a function argument, see [`crate::registry::Registry::value`].
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Result<Self, Error>;
+
+    /// The value of an argument that was left out, if it may be.
+    fn missing() -> Option<Self> {
+        None
+    }
+}
+
+/// Turns what a function returns into a value.
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+/// The value inside any typed wrappers, which conversions see through.
+fn untyped(value: &Value) -> &Value {
+    match value {
+        Value::Typed(t) => untyped(&t.value),
+        _ => value,
+    }
+}
+
+fn mismatch(expected: &'static str, found: &Value) -> Error {
+    Error::Type {
+        expected,
+        found: found.type_name(),
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match untyped(value) {
+            Value::String(s) => Ok(s.clone()),
+            _ => Err(mismatch("string", value)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match untyped(value) {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(mismatch("boolean", value)),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match untyped(value) {
+            Value::Number(n) => Ok(*n),
+            _ => Err(mismatch("number", value)),
+        }
+    }
+}
+
+macro_rules! impl_narrow_number {
+    ($t:ty, $expected:literal) => {
+        impl FromValue for $t {
+            fn from_value(value: &Value) -> Result<Self, Error> {
+                let n = i64::from_value(value)?;
+                n.try_into().map_err(|_| Error::OutOfRange {
+                    value: n,
+                    expected: $expected,
+                })
+            }
+        }
+    };
+}
+
+impl_narrow_number!(i32, "a 32-bit number");
+impl_narrow_number!(usize, "a count");
+
+/// Numbers are accepted where floats are expected.
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match untyped(value) {
+            Value::Float(f) => Ok(*f),
+            Value::Number(n) => Ok(*n as Self),
+            _ => Err(mismatch("float", value)),
+        }
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        f64::from_value(value).map(|f| f as Self)
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match untyped(value) {
+            Value::Array(values) => values.iter().map(|e| T::from_value(&e.value)).collect(),
+            _ => Err(mismatch("array", value)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match untyped(value) {
+            Value::Object(records) => records
+                .iter()
+                .map(|r| Ok((r.id.clone(), T::from_value(&r.value)?)))
+                .collect(),
+            _ => Err(mismatch("object", value)),
+        }
+    }
+}
+
+/// `null` and left out arguments are `None`.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        match untyped(value) {
+            Value::Null => Ok(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self) -> Value {
+        Value::Number(self.into())
+    }
+}
+
+/// Counts beyond `i64::MAX` saturate.
+impl IntoValue for usize {
+    fn into_value(self) -> Value {
+        Value::Number(self.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        Value::Float(self.into())
+    }
+}
+
+impl IntoValue for Typed {
+    fn into_value(self) -> Value {
+        Value::Typed(self)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::array(self.into_iter().map(IntoValue::into_value))
+    }
+}
+
+/// Records are sorted by key, so the result does not depend on the map's
+/// order.
+impl<T: IntoValue> IntoValue for HashMap<String, T> {
+    fn into_value(self) -> Value {
+        let mut records = self
+            .into_iter()
+            .map(|(id, value)| Record {
+                id,
+                value: value.into_value(),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        records.sort_by(|a, b| a.id.cmp(&b.id));
+        Value::Object(records)
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::Null, IntoValue::into_value)
+    }
+}
+
+/// Tuples are arrays of a fixed length.
+macro_rules! impl_tuple {
+    ($expected:literal; $($t:ident $v:ident),*) => {
+        impl<$($t: FromValue),*> FromValue for ($($t,)*) {
+            fn from_value(value: &Value) -> Result<Self, Error> {
+                match untyped(value) {
+                    Value::Array(values) => match values.as_slice() {
+                        [$($v),*] => Ok(($($t::from_value(&$v.value)?,)*)),
+                        _ => Err(mismatch($expected, value)),
+                    },
+                    _ => Err(mismatch($expected, value)),
+                }
+            }
+        }
+
+        impl<$($t: IntoValue),*> IntoValue for ($($t,)*) {
+            fn into_value(self) -> Value {
+                let ($($v,)*) = self;
+                Value::array([$($v.into_value()),*])
+            }
+        }
+    };
+}
+
+impl_tuple!("array of 2 values"; A a, B b);
+impl_tuple!("array of 3 values"; A a, B b, C c);
+impl_tuple!("array of 4 values"; A a, B b, C c, D d);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() -> Result<(), Error> {
+        assert_eq!(String::from_value(&Value::String("a".to_string()))?, "a");
+        assert_eq!(i64::from_value(&Value::Number(3))?, 3);
+        assert_eq!(i32::from_value(&Value::Number(-3))?, -3);
+        assert!((f64::from_value(&Value::Number(3))? - 3.0).abs() < f64::EPSILON);
+        assert!((f32::from_value(&Value::Float(0.5))? - 0.5).abs() < f32::EPSILON);
+        assert!(bool::from_value(&Value::Bool(true))?);
+
+        let typed = Value::Typed(Typed {
+            kind: "std_url".to_string(),
+            value: Box::new(Value::String("localhost".to_string())),
+        });
+        assert_eq!(String::from_value(&typed)?, "localhost");
+        assert_eq!(Value::from_value(&typed)?, typed);
+
+        assert_eq!("x".into_value(), Value::String("x".to_string()));
+        assert_eq!(7i32.into_value(), Value::Number(7));
+
+        Ok(())
+    }
+
+    #[test]
+    fn containers() -> Result<(), Error> {
+        let numbers = Value::array(vec![Value::Number(1), Value::Number(2)]);
+        assert_eq!(Vec::<i64>::from_value(&numbers)?, [1, 2]);
+        assert_eq!(<(i64, f64)>::from_value(&numbers)?, (1, 2.0));
+        assert_eq!(vec![1i64, 2].into_value(), numbers);
+        assert_eq!(
+            (1i64, "a").into_value(),
+            Value::array(vec![Value::Number(1), Value::String("a".to_string())])
+        );
+
+        let object = Value::Object(vec![
+            Record {
+                id: "b".to_string(),
+                value: Value::Number(2),
+                ..Default::default()
+            },
+            Record {
+                id: "a".to_string(),
+                value: Value::Number(1),
+                ..Default::default()
+            },
+        ]);
+        let map = HashMap::<String, i64>::from_value(&object)?;
+        assert_eq!(map.get("a"), Some(&1));
+        assert_eq!(map.get("b"), Some(&2));
+        let Value::Object(records) = map.into_value() else {
+            unreachable!()
+        };
+        assert_eq!(records[0].id, "a");
+
+        assert_eq!(Option::<i64>::from_value(&Value::Null)?, None);
+        assert_eq!(Option::<i64>::from_value(&Value::Number(1))?, Some(1));
+        assert_eq!(Option::<i64>::missing(), Some(None));
+        assert_eq!(i64::missing(), None);
+        assert_eq!(None::<i64>.into_value(), Value::Null);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mismatches() {
+        let error = |e: Error| e.to_string();
+
+        assert_eq!(
+            error(i64::from_value(&Value::Float(1.5)).unwrap_err()),
+            "expected number, found float"
+        );
+        assert_eq!(
+            error(Vec::<String>::from_value(&Value::array(vec![Value::Null])).unwrap_err()),
+            "expected string, found null"
+        );
+        assert_eq!(
+            error(<(i64, i64)>::from_value(&Value::array(vec![])).unwrap_err()),
+            "expected array of 2 values, found array"
+        );
+        assert_eq!(
+            error(HashMap::<String, i64>::from_value(&Value::Bool(true)).unwrap_err()),
+            "expected object, found boolean"
+        );
+        assert_eq!(
+            error(i32::from_value(&Value::Number(1 << 40)).unwrap_err()),
+            "1099511627776 is out of range for a 32-bit number"
+        );
+        assert_eq!(
+            error(usize::from_value(&Value::Number(-1)).unwrap_err()),
+            "-1 is out of range for a count"
+        );
+    }
+}
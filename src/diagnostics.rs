@@ -0,0 +1,621 @@
+use crate::lexer::{self, FilePos};
+use crate::{eval, parser, printer, serde};
+use std::fmt;
+
+/// A 1-based line and column, the column counted in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LineCol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Finds the line and column of the byte `offset` in `source`. Offsets past
+/// the end point just after the last character.
+pub fn line_col(source: &str, offset: usize) -> LineCol {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    LineCol {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+    }
+}
+
+/// The message of a parse error on one line, with the earlier position it
+/// refers to as a line and column in `source`:
+///
+/// ```text
+/// duplicate key a, first defined at 1:1
+/// ```
+pub fn parse_message(error: &parser::ErrorTypes, source: &str) -> String {
+    match error {
+        parser::ErrorTypes::Unclosed { opened, .. } => {
+            format!("{error} opened at {}", line_col(source, opened.start))
+        }
+        parser::ErrorTypes::DuplicateKey { first, .. } => {
+            format!(
+                "{error}, first defined at {}",
+                line_col(source, first.start)
+            )
+        }
+        parser::ErrorTypes::DuplicateArgument { first, .. } => {
+            format!("{error}, first at {}", line_col(source, first.start))
+        }
+        _ => error.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub pos: FilePos,
+    pub message: String,
+    /// Primary labels mark the cause of the error and are underlined with
+    /// `^`, secondary ones give context and use `-`.
+    pub primary: bool,
+}
+
+/// An error ready to be shown to a user, with the parts of the source that
+/// explain it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    #[must_use]
+    pub fn primary(mut self, pos: FilePos, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            pos,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn secondary(mut self, pos: FilePos, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            pos,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    #[must_use]
+    pub fn help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Renders the diagnostic against `source`, read from the file `name`:
+    ///
+    /// ```text
+    /// error: duplicate key a
+    ///  --> config.alt:2:1
+    ///   |
+    /// 1 | a = 1
+    ///   | - first defined here
+    /// 2 | a = 2
+    ///   | ^ defined again here
+    /// ```
+    pub fn render(&self, source: &str, name: &str) -> String {
+        let mut out = format!("error: {}\n", self.message);
+        let lines = source.split('\n').collect::<Vec<&str>>();
+
+        let spans = self
+            .labels
+            .iter()
+            .map(|l| {
+                let start = line_col(source, l.pos.start);
+                let mut end = line_col(source, l.pos.end.max(l.pos.start));
+                // a span ending with a newline stays on the line it ends
+                if end.line > start.line && end.column == 1 {
+                    end = line_col(source, l.pos.end - 1);
+                    end.column += 1;
+                }
+                (l, start, end)
+            })
+            .collect::<Vec<_>>();
+
+        let mut shown = spans
+            .iter()
+            .flat_map(|(_, start, end)| start.line..=end.line)
+            .collect::<Vec<usize>>();
+        shown.sort_unstable();
+        shown.dedup();
+
+        let width = shown.last().map_or(1, |n| n.to_string().len());
+        let gutter = " ".repeat(width);
+
+        if let Some((_, start, _)) = spans
+            .iter()
+            .find(|(l, ..)| l.primary)
+            .or_else(|| spans.first())
+        {
+            out += &format!("{gutter}--> {name}:{start}\n");
+        }
+
+        if !shown.is_empty() {
+            out += &format!("{gutter} |\n");
+        }
+        let text = |n: usize| {
+            lines
+                .get(n - 1)
+                .map_or("", |l| l.strip_suffix('\r').unwrap_or(l))
+        };
+        let mut previous = None;
+        for &n in &shown {
+            match previous {
+                // a single hidden line is shown rather than elided
+                Some(p) if p + 2 == n => {
+                    out += format!("{:>width$} | {}", p + 1, text(p + 1)).trim_end();
+                    out += "\n";
+                }
+                Some(p) if p + 2 < n => out += "...\n",
+                _ => (),
+            }
+            previous = Some(n);
+
+            let text = text(n);
+            out += format!("{n:>width$} | {text}").trim_end();
+            out += "\n";
+
+            for (label, start, end) in &spans {
+                if !(start.line..=end.line).contains(&n) {
+                    continue;
+                }
+                let from = if n == start.line { start.column } else { 1 };
+                let to = if n == end.line {
+                    end.column
+                } else {
+                    text.chars().count() + 1
+                };
+                let indent = text
+                    .chars()
+                    .take(from - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect::<String>();
+                let mark = if label.primary { "^" } else { "-" };
+                let marks = mark.repeat(to.saturating_sub(from).max(1));
+
+                let mut underline = format!("{gutter} | {indent}{marks}");
+                if n == end.line && !label.message.is_empty() {
+                    underline += " ";
+                    underline += &label.message;
+                }
+                out += underline.trim_end();
+                out += "\n";
+            }
+        }
+
+        if !self.notes.is_empty() || self.help.is_some() {
+            out += &format!("{gutter} |\n");
+        }
+        for note in &self.notes {
+            out += &format!("{gutter} = note: {note}\n");
+        }
+        if let Some(help) = &self.help {
+            out += &format!("{gutter} = help: {help}\n");
+        }
+        out
+    }
+}
+
+impl From<&lexer::Error> for Diagnostic {
+    fn from(e: &lexer::Error) -> Self {
+        let diagnostic = Self::new(e.error.to_string()).primary(e.pos, "");
+        match e.error {
+            lexer::ErrorTypes::UnterminatedString => {
+                diagnostic.help("close the string with a matching quote")
+            }
+            lexer::ErrorTypes::UnterminatedComment => diagnostic.help("close the comment with */"),
+            lexer::ErrorTypes::InvalidEscape => {
+                diagnostic.note("valid escapes are \\\" \\\\ \\n \\t \\r \\0 and \\u{...}")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+impl From<&parser::Error> for Diagnostic {
+    fn from(e: &parser::Error) -> Self {
+        match &e.error {
+            parser::ErrorTypes::Lex(error) => Self::from(&lexer::Error {
+                error: error.clone(),
+                pos: e.pos,
+            }),
+            parser::ErrorTypes::DuplicateKey { key, first } => {
+                Self::new(format!("duplicate key {key}"))
+                    .primary(e.pos, "defined again here")
+                    .secondary(*first, "first defined here")
+                    .help("merge both definitions, or parse with another merge policy")
+            }
+            parser::ErrorTypes::DuplicateArgument { name, first } => {
+                Self::new(format!("argument {name} is passed twice"))
+                    .primary(e.pos, "passed again here")
+                    .secondary(*first, "first passed here")
+            }
+            parser::ErrorTypes::ConflictingKey(key) => Self::new(e.error.to_string())
+                .primary(e.pos, "")
+                .note(format!("{key} cannot hold both an object and a value")),
+            parser::ErrorTypes::Unexpected {
+                found,
+                expected,
+                within,
+            } => {
+                let diagnostic = Self::new(format!("unexpected {found} {within}"))
+                    .primary(e.pos, format!("expected {}", parser::describe(expected)));
+                match within {
+                    parser::Within::Object(opened) => {
+                        diagnostic.secondary(*opened, "object opened here")
+                    }
+                    parser::Within::Array(opened) => {
+                        diagnostic.secondary(*opened, "array opened here")
+                    }
+                    parser::Within::Arguments(opened) => {
+                        diagnostic.secondary(*opened, "argument list opened here")
+                    }
+                    _ => diagnostic,
+                }
+            }
+            parser::ErrorTypes::TooDeep => Self::new(e.error.to_string())
+                .primary(e.pos, "one level too deep")
+                .help("flatten the document, or split it into several"),
+            parser::ErrorTypes::MissingValue(_) => {
+                Self::new(e.error.to_string()).primary(e.pos, "expected a value")
+            }
+            parser::ErrorTypes::Unclosed { close, opened } => {
+                let what = parser::closes(close);
+                Self::new(format!("unclosed {what}"))
+                    .primary(e.pos, format!("expected {close}"))
+                    .secondary(*opened, format!("{what} opened here"))
+            }
+        }
+    }
+}
+
+impl From<&eval::Error> for Diagnostic {
+    fn from(e: &eval::Error) -> Self {
+        match e {
+            eval::Error::DuplicateKey(conflict) => {
+                let diagnostic = Self::new(format!("duplicate key {}", conflict.id))
+                    .primary(conflict.second, "defined again here");
+                if conflict.first == FilePos::default() {
+                    diagnostic
+                } else {
+                    diagnostic.secondary(conflict.first, "first defined here")
+                }
+            }
+            eval::Error::Call { function, pos, .. } => match e.root() {
+                eval::Error::InvalidFunction {
+                    name,
+                    suggestion: Some(suggestion),
+                } => Self::new(format!("invalid function {name}"))
+                    .primary(*pos, "not defined")
+                    .help(format!("did you mean {suggestion}?")),
+                eval::Error::MissingVariable { name, .. } => Self::new(e.root().to_string())
+                    .primary(*pos, format!("in {function}"))
+                    .help(format!("set {name}, or use @env with a default")),
+                eval::Error::Argument { pos: at, .. } if *at != FilePos::default() => {
+                    Self::new(e.root().to_string())
+                        .primary(*at, format!("passed to {function}"))
+                        .secondary(*pos, "called here")
+                }
+                root => Self::new(root.to_string()).primary(*pos, format!("in {function}")),
+            },
+            eval::Error::Unparsed(pos) => {
+                Self::new("cannot evaluate a document with syntax errors")
+                    .primary(*pos, "this value failed to parse")
+            }
+            eval::Error::ReferenceCycle {
+                cycle,
+                reference,
+                definition,
+            } => Self::new(e.to_string())
+                .primary(*reference, format!("refers back to {}", cycle[0]))
+                .secondary(*definition, format!("{} defined here", cycle[0])),
+            eval::Error::UnresolvedReference { path, pos } => {
+                Self::new(e.to_string()).primary(*pos, format!("{path} is not in the document"))
+            }
+            eval::Error::InvalidFunction { .. }
+            | eval::Error::Eval(_)
+            | eval::Error::Arity { .. }
+            | eval::Error::UnexpectedArgument(_)
+            | eval::Error::Type { .. }
+            | eval::Error::OutOfRange { .. }
+            | eval::Error::MissingVariable { .. }
+            | eval::Error::Argument { .. }
+            | eval::Error::Syntax(_)
+            | eval::Error::Document { .. } => Self::new(e.to_string()),
+        }
+    }
+}
+
+/// Renders `e`, raised evaluating `source` read from the file `name`. An
+/// error in a document loaded by `#include` or `@import` is shown in that
+/// document, with a note for every load that led to it.
+pub fn render_eval(e: &eval::Error, source: &str, name: &str) -> String {
+    let (mut e, mut source, mut name) = (e, source, name.to_string());
+    let mut chain = Vec::new();
+    while let eval::Error::Call {
+        function,
+        pos,
+        error,
+    } = e
+    {
+        let eval::Error::Document {
+            path,
+            source: loaded,
+            error: inner,
+        } = error.root()
+        else {
+            break;
+        };
+        chain.push(format!(
+            "{path} loaded by {function} at {name}:{}",
+            line_col(source, pos.start)
+        ));
+        (e, source, name) = (&**inner, loaded.as_str(), path.clone());
+    }
+
+    let with_chain = |diagnostic: Diagnostic| {
+        chain
+            .iter()
+            .rev()
+            .fold(diagnostic, |diagnostic, note| diagnostic.note(note.clone()))
+            .render(source, &name)
+    };
+    match e {
+        eval::Error::Syntax(errors) => errors
+            .iter()
+            .map(|e| with_chain(Diagnostic::from(e)))
+            .collect(),
+        _ => with_chain(Diagnostic::from(e)),
+    }
+}
+
+impl From<&printer::Error> for Diagnostic {
+    fn from(e: &printer::Error) -> Self {
+        match e {
+            printer::Error::UnsupportedComment(pos) => {
+                Self::new("comment would be lost when formatting")
+                    .primary(*pos, "")
+                    .note("only comments between records are kept")
+            }
+            printer::Error::Unparsed(pos) => {
+                Self::new("cannot format a document with syntax errors")
+                    .primary(*pos, "this value failed to parse")
+            }
+            _ => Self::new(e.to_string()),
+        }
+    }
+}
+
+impl From<&serde::Error> for Diagnostic {
+    fn from(e: &serde::Error) -> Self {
+        match e {
+            serde::Error::Parse(e) => e.into(),
+            serde::Error::Eval(e) => e.into(),
+            serde::Error::Print(e) => e.into(),
+            serde::Error::Field { key, error, .. } => {
+                let diagnostic = Self::new(error.to_string());
+                match e.pos() {
+                    Some(pos) => diagnostic.primary(pos, format!("in {key}")),
+                    None => diagnostic.note(format!("in {key}")),
+                }
+            }
+            _ => Self::new(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, goodies, lexer::tokenize, parser::parse};
+
+    #[test]
+    fn lines_and_columns() {
+        let source = "a = 1\nb = \"żółw\" c\n";
+        assert_eq!(line_col(source, 0), LineCol { line: 1, column: 1 });
+        assert_eq!(line_col(source, 6), LineCol { line: 2, column: 1 });
+        // "żółw" takes seven bytes but four columns
+        assert_eq!(
+            line_col(source, 18),
+            LineCol {
+                line: 2,
+                column: 10
+            }
+        );
+        assert_eq!(line_col(source, 100), LineCol { line: 3, column: 1 });
+    }
+
+    #[test]
+    fn parse_messages() -> Result<(), lexer::Error> {
+        let message = |source| -> Result<String, lexer::Error> {
+            let error = parse(&tokenize(source)?).unwrap_err();
+            Ok(parse_message(&error.error, source))
+        };
+        assert_eq!(
+            message("a = 1\nb = [\n    1\n")?,
+            "expected `]` to close array opened at 2:5"
+        );
+        assert_eq!(
+            message("a = 1\na = 2")?,
+            "duplicate key a, first defined at 1:1"
+        );
+        assert_eq!(
+            message("x = @f(a = 1,\n       a = 2)")?,
+            "argument a is passed twice, first at 1:8"
+        );
+        assert_eq!(message("a = ")?, "missing value after `a =`");
+        Ok(())
+    }
+
+    #[test]
+    fn render_parse_error() -> Result<(), lexer::Error> {
+        let source = "x = 1\ny = {\n    a 2\n}\n";
+        let error = parse(&tokenize(source)?).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "test.alt"),
+            "error: unexpected number after `a`
+ --> test.alt:3:7
+  |
+3 |     a 2
+  |       ^ expected `=` or `.`
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_array_errors() -> Result<(), lexer::Error> {
+        let source = "x = 1\nports = [80 443 = 8080]\n";
+        let error = parse(&tokenize(source)?).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "test.alt"),
+            "error: unexpected `=` in array
+ --> test.alt:2:17
+  |
+2 | ports = [80 443 = 8080]
+  |                 ^ expected value, `,` or `]`
+  |         - array opened here
+"
+        );
+
+        let source = "x = [1\n2\n";
+        let error = parse(&tokenize(source)?).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "test.alt"),
+            "error: unclosed array
+ --> test.alt:3:1
+  |
+1 | x = [1
+  |     - array opened here
+2 | 2
+3 |
+  | ^ expected `]`
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_secondary_labels() -> Result<(), lexer::Error> {
+        let source = "a = 1\nb = 2\n\n\nc = 3\na = 4";
+        let error = parse(&tokenize(source)?).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "test.alt"),
+            "error: duplicate key a
+ --> test.alt:6:1
+  |
+1 | a = 1
+  | - first defined here
+...
+6 | a = 4
+  | ^ defined again here
+  |
+  = help: merge both definitions, or parse with another merge policy
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_multi_line_span() {
+        let source = "x = \"\"\"\n  one\n  two\n";
+        let diagnostic = Diagnostic::new("unterminated string")
+            .primary(FilePos { start: 4, end: 19 }, "never closed")
+            .note("multi-line strings end at the next triple quote");
+        assert_eq!(
+            diagnostic.render(source, "test.alt"),
+            "error: unterminated string
+ --> test.alt:1:5
+  |
+1 | x = \"\"\"
+  |     ^^^
+2 |   one
+  | ^^^^^
+3 |   two
+  | ^^^^^ never closed
+  |
+  = note: multi-line strings end at the next triple quote
+"
+        );
+    }
+
+    #[test]
+    fn render_reference_cycle() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "timeout = @ref retry.timeout\nretry = {\n  timeout = @ref timeout\n}";
+        let parsed = parse(&tokenize(source)?)?;
+        let error = goodies::Evaluator::default().eval(&parsed).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "test.alt"),
+            "error: reference cycle: timeout -> retry.timeout -> timeout
+ --> test.alt:3:13
+  |
+1 | timeout = @ref retry.timeout
+  | ------- timeout defined here
+2 | retry = {
+3 |   timeout = @ref timeout
+  |             ^^^^^^^^^^^^ refers back to timeout
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_eval_error() -> Result<(), Box<dyn std::error::Error>> {
+        let source = "a = 1\nb = [1 @std_url 2]";
+        let parsed = parse(&tokenize(source)?)?;
+        let error = goodies::Evaluator::default().eval(&parsed).unwrap_err();
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "test.alt"),
+            "error: argument 1: expected string, found number
+ --> test.alt:2:17
+  |
+2 | b = [1 @std_url 2]
+  |                 ^ passed to @std_url
+  |        -------- called here
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_without_labels() {
+        let diagnostic = Diagnostic::new("expected object").help("wrap it in {}");
+        assert_eq!(
+            diagnostic.render("", "test.alt"),
+            "error: expected object\n  |\n  = help: wrap it in {}\n"
+        );
+    }
+}
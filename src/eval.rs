@@ -1,79 +1,626 @@
-use crate::ast::{Call, Record, RecordOrCall, Typed, Value};
+use crate::ast::{Argument, Call, Element, Record, RecordOrCall, Reference, Typed, Value};
+use crate::lexer::FilePos;
+use crate::merge::{self, Conflict, MergePolicy};
+use crate::parser;
+use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::fmt::Display;
 
 #[derive(Debug)]
 pub enum Error {
-    InvalidFunction,
+    /// A call to a function that does not exist, with a similarly named one
+    /// if there is any.
+    InvalidFunction {
+        name: String,
+        suggestion: Option<String>,
+    },
+    DuplicateKey(Conflict),
+    /// The document has a value that failed to parse.
+    Unparsed(FilePos),
     Eval(Box<dyn StdError>),
+    /// A function was called with the wrong number of positional arguments,
+    /// `min` to `max` of them, or at least `min` without a `max`.
+    Arity {
+        min: usize,
+        max: Option<usize>,
+        found: usize,
+    },
+    /// A function was passed a named argument it does not take.
+    UnexpectedArgument(String),
+    /// A value of the wrong type.
+    Type {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// A number too large or too small for what it is used for.
+    OutOfRange {
+        value: i64,
+        expected: &'static str,
+    },
+    /// An environment variable that must be set is not, with the dotted key
+    /// of the record whose value needed it once that is known.
+    MissingVariable {
+        name: String,
+        record: Option<String>,
+    },
+    /// An error converting the positional argument at `position`, counted
+    /// from 1, written at `pos` once the call it was passed to is known.
+    Argument {
+        position: usize,
+        pos: FilePos,
+        error: Box<Error>,
+    },
+    /// A document that failed to parse, with all its syntax errors.
+    Syntax(Vec<parser::Error>),
+    /// An error in another document, read from `path`, such as one loaded by
+    /// `#include`. Positions in `error` are in that document's `source`.
+    Document {
+        path: String,
+        source: String,
+        error: Box<Error>,
+    },
+    /// A reference that leads back to itself. `cycle` holds the keys from
+    /// the record at `definition` to the reference at `reference` that
+    /// refers to it again.
+    ReferenceCycle {
+        cycle: Vec<String>,
+        reference: FilePos,
+        definition: FilePos,
+    },
+    /// A reference to a key that is not in the document.
+    UnresolvedReference {
+        path: String,
+        pos: FilePos,
+    },
+    /// An error returned by a function, with the call that failed.
+    Call {
+        function: String,
+        pos: FilePos,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The position in the source document that caused the error, if known.
+    pub fn pos(&self) -> Option<FilePos> {
+        match self {
+            Self::DuplicateKey(conflict) => Some(conflict.second),
+            // an argument that failed is more precise than the call
+            Self::Call { pos, error, .. } => match **error {
+                Self::Argument { .. } => error.pos().or(Some(*pos)),
+                _ => Some(*pos),
+            },
+            Self::Argument { pos, .. } => Some(*pos).filter(|pos| *pos != FilePos::default()),
+            Self::Unparsed(pos)
+            | Self::UnresolvedReference { pos, .. }
+            | Self::ReferenceCycle { reference: pos, .. } => Some(*pos),
+            Self::InvalidFunction { .. }
+            | Self::Eval(_)
+            | Self::Arity { .. }
+            | Self::UnexpectedArgument(_)
+            | Self::Type { .. }
+            | Self::OutOfRange { .. }
+            | Self::MissingVariable { .. }
+            | Self::Syntax(_)
+            | Self::Document { .. } => None,
+        }
+    }
+
+    /// The error without the calls it was raised in.
+    pub fn root(&self) -> &Self {
+        match self {
+            Self::Call { error, .. } => error.root(),
+            _ => self,
+        }
+    }
+
+    /// Adds `id` in front of the record a missing variable was needed by.
+    fn in_record(mut self, id: &str) -> Self {
+        let mut error = &mut self;
+        loop {
+            match error {
+                Self::Call { error: inner, .. } | Self::Argument { error: inner, .. } => {
+                    error = inner;
+                }
+                Self::MissingVariable { record, .. } => {
+                    *record = Some(match record.take() {
+                        Some(key) => format!("{id}.{key}"),
+                        None => id.to_string(),
+                    });
+                    break;
+                }
+                _ => break,
+            }
+        }
+        self
+    }
+
+    fn in_call(self, sigil: char, call: &Call) -> Self {
+        // functions only know which argument failed, the call where it is
+        let error = match self {
+            Self::Argument {
+                position,
+                pos,
+                error,
+            } => Self::Argument {
+                position,
+                pos: (call.args.iter().filter(|arg| arg.name.is_none()))
+                    .nth(position.saturating_sub(1))
+                    .map_or(pos, |arg| arg.pos),
+                error,
+            },
+            error => error,
+        };
+        Self::Call {
+            function: format!("{sigil}{}", call.function),
+            pos: call.pos,
+            error: Box::new(error),
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::InvalidFunction => write!(f, "invalid function"),
+            Self::InvalidFunction { name, suggestion } => {
+                write!(f, "invalid function {name}")?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean {suggestion}?")?;
+                }
+                Ok(())
+            }
+            Self::DuplicateKey(conflict) => write!(f, "{conflict}"),
+            Self::Unparsed(pos) => write!(f, "value at {pos} failed to parse"),
             Self::Eval(err) => write!(f, "function eval error: {err}"),
+            Self::Arity { min, max, found } => {
+                let s = if *min == 1 { "" } else { "s" };
+                match max {
+                    Some(max) if max == min => {
+                        write!(f, "expected {min} argument{s}, found {found}")
+                    }
+                    Some(max) => write!(f, "expected {min} to {max} arguments, found {found}"),
+                    None => write!(f, "expected at least {min} argument{s}, found {found}"),
+                }
+            }
+            Self::UnexpectedArgument(name) => write!(f, "unexpected argument {name}"),
+            Self::Type { expected, found } => write!(f, "expected {expected}, found {found}"),
+            Self::OutOfRange { value, expected } => {
+                write!(f, "{value} is out of range for {expected}")
+            }
+            Self::MissingVariable { name, record } => {
+                write!(f, "environment variable {name} is not set")?;
+                if let Some(record) = record {
+                    write!(f, ", required by {record}")?;
+                }
+                Ok(())
+            }
+            Self::Argument {
+                position, error, ..
+            } => write!(f, "argument {position}: {error}"),
+            Self::Syntax(errors) => {
+                write!(f, "syntax error")?;
+                if let Some(first) = errors.first() {
+                    write!(f, ": {first}")?;
+                }
+                if errors.len() > 1 {
+                    write!(f, " and {} more", errors.len() - 1)?;
+                }
+                Ok(())
+            }
+            Self::Document { path, error, .. } => write!(f, "{error}, in {path}"),
+            Self::ReferenceCycle { cycle, .. } => {
+                write!(f, "reference cycle: {}", cycle.join(" -> "))
+            }
+            Self::UnresolvedReference { path, .. } => {
+                write!(f, "unresolved reference to {path}")
+            }
+            // the error already names the function
+            Self::Call { error, .. } if matches!(**error, Self::InvalidFunction { .. }) => {
+                write!(f, "{error}")
+            }
+            Self::Call {
+                function, error, ..
+            } => write!(f, "{error} in {function}"),
         }
     }
 }
 
 impl StdError for Error {}
 
-pub trait Evaluator {
-    fn value_function_eval(&mut self, call: &Call) -> Result<Value, Error>;
-    fn record_function_eval(&mut self, call: &Call) -> Result<Option<Record>, Error>;
+/// The argument of a function taking a single positional one.
+pub fn single(call: &Call) -> Result<&Value, Error> {
+    if let Some(name) = call.args.iter().find_map(|arg| arg.name.as_ref()) {
+        return Err(Error::UnexpectedArgument(name.clone()));
+    }
+    call.single().ok_or(Error::Arity {
+        min: 1,
+        max: Some(1),
+        found: call.args.len(),
+    })
+}
 
-    fn eval(&mut self, root: &Value) -> Result<Value, Error> {
-        match root {
-            Value::Call(ref call) => {
-                let boxed = Box::new(self.eval(call.value.as_ref())?);
-                self.value_function_eval(&Call {
-                    value: boxed,
-                    function: call.function.to_string(),
-                })
-            }
-            Value::ObjectWithCalls(object) => {
-                let mut obj = Vec::new();
-                for record in object {
-                    match record {
-                        RecordOrCall::Record(record) => obj.push(Record {
-                            id: record.id.clone(),
-                            value: self.eval(&record.value)?,
-                        }),
-                        RecordOrCall::Call(call) => {
-                            let boxed = Box::new(self.eval(call.value.as_ref())?);
-                            let optional = self.record_function_eval(&Call {
-                                function: call.function.to_string(),
-                                value: boxed,
-                            })?;
-                            if let Some(rec) = optional {
-                                obj.push(rec);
+/// `call` with its arguments evaluated.
+fn eval_args<E>(evaluator: &mut E, call: &Call, doc: &mut Document) -> Result<Call, Error>
+where
+    E: Evaluator + ?Sized,
+{
+    let args = call
+        .args
+        .iter()
+        .map(|arg| {
+            Ok(Argument {
+                name: arg.name.clone(),
+                value: eval_value(evaluator, &arg.value, None, doc)?,
+                pos: arg.pos,
+                ..Default::default()
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Call {
+        function: call.function.clone(),
+        args,
+        pos: call.pos,
+    })
+}
+
+/// Where a value is in the document, as the indices of the entries of each
+/// object on the way to it and the keys of the records among them.
+#[derive(Clone, Default)]
+struct Location {
+    indices: Vec<usize>,
+    keys: Vec<String>,
+}
+
+impl Location {
+    fn child(&self, index: usize, id: &str) -> Self {
+        let mut child = self.clone();
+        child.indices.push(index);
+        child.keys.push(id.to_string());
+        child
+    }
+}
+
+/// A record or record call that is being evaluated.
+struct Evaluating {
+    indices: Vec<usize>,
+    /// The dotted key of the record, or the call.
+    name: String,
+    pos: FilePos,
+}
+
+/// One document being evaluated. Records and record calls are evaluated
+/// once, either in order or earlier when a reference needs them, and kept
+/// by their indices.
+struct Document<'a> {
+    root: &'a Value,
+    values: HashMap<Vec<usize>, Value>,
+    records: HashMap<Vec<usize>, Vec<Record>>,
+    evaluating: Vec<Evaluating>,
+}
+
+impl Document<'_> {
+    /// The cycle error if `indices` is already being evaluated, which means
+    /// `reference` needs its own value.
+    fn is_evaluating(&self, indices: &[usize]) -> bool {
+        self.evaluating.iter().any(|e| e.indices == indices)
+    }
+
+    fn check_cycle(&self, indices: &[usize], reference: &Reference) -> Result<(), Error> {
+        let Some(first) = self.evaluating.iter().position(|e| e.indices == indices) else {
+            return Ok(());
+        };
+        let cycle = &self.evaluating[first..];
+        Err(Error::ReferenceCycle {
+            cycle: cycle
+                .iter()
+                .chain(std::iter::once(&cycle[0]))
+                .map(|e| e.name.clone())
+                .collect(),
+            reference: reference.pos,
+            definition: cycle[0].pos,
+        })
+    }
+}
+
+fn eval_record<E>(
+    evaluator: &mut E,
+    record: &Record,
+    at: Location,
+    doc: &mut Document,
+) -> Result<Value, Error>
+where
+    E: Evaluator + ?Sized,
+{
+    if let Some(value) = doc.values.get(&at.indices) {
+        return Ok(value.clone());
+    }
+    doc.evaluating.push(Evaluating {
+        indices: at.indices.clone(),
+        name: at.keys.join("."),
+        pos: record.pos,
+    });
+    let value = eval_value(evaluator, &record.value, Some(&at), doc);
+    doc.evaluating.pop();
+    let value = value.map_err(|e| e.in_record(&record.id))?;
+    doc.values.insert(at.indices, value.clone());
+    Ok(value)
+}
+
+/// The records added by a record call, kept by `indices` if it has them.
+fn eval_record_call<E>(
+    evaluator: &mut E,
+    call: &Call,
+    indices: Option<Vec<usize>>,
+    doc: &mut Document,
+) -> Result<Vec<Record>, Error>
+where
+    E: Evaluator + ?Sized,
+{
+    let Some(indices) = indices else {
+        let evaluated = eval_args(evaluator, call, doc)?;
+        return evaluator
+            .record_function_eval(&evaluated)
+            .map_err(|e| e.in_call('#', call));
+    };
+    if let Some(records) = doc.records.get(&indices) {
+        return Ok(records.clone());
+    }
+    doc.evaluating.push(Evaluating {
+        indices: indices.clone(),
+        name: format!("#{}", call.function),
+        pos: call.pos,
+    });
+    let records = eval_args(evaluator, call, doc).and_then(|evaluated| {
+        evaluator
+            .record_function_eval(&evaluated)
+            .map_err(|e| e.in_call('#', call))
+    });
+    doc.evaluating.pop();
+    let mut records = records?;
+    for record in &mut records {
+        if record.pos == FilePos::default() {
+            record.pos = call.pos;
+        }
+    }
+    doc.records.insert(indices, records.clone());
+    Ok(records)
+}
+
+/// The value `reference` refers to. Where record calls add a key, it is
+/// merged with the records written for it as the object would merge them,
+/// leaving out calls being evaluated, which are what refers to it.
+fn resolve<E>(evaluator: &mut E, reference: &Reference, doc: &mut Document) -> Result<Value, Error>
+where
+    E: Evaluator + ?Sized,
+{
+    let unresolved = || Error::UnresolvedReference {
+        path: reference.path.join("."),
+        pos: reference.pos,
+    };
+    let mut object = doc.root;
+    let mut at = Location::default();
+    for (depth, id) in reference.path.iter().enumerate() {
+        let Value::ObjectWithCalls(entries) = object else {
+            return Err(unresolved());
+        };
+        let rest = &reference.path[depth + 1..];
+
+        let mut added = Vec::new();
+        let mut pending = None;
+        for (i, entry) in entries.iter().enumerate() {
+            let RecordOrCall::Call(call) = entry else {
+                continue;
+            };
+            let mut indices = at.indices.clone();
+            indices.push(i);
+            if doc.is_evaluating(&indices) {
+                pending.get_or_insert(indices);
+                continue;
+            }
+            let records = eval_record_call(evaluator, call, Some(indices), doc)?;
+            added.extend(records.into_iter().filter(|r| r.id == *id).map(|r| (i, r)));
+        }
+        if !added.is_empty() {
+            let value = merged(evaluator, entries, &at, id, added, reference, doc)?;
+            return index(value, rest).ok_or_else(unresolved);
+        }
+
+        let mut written = entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| match entry {
+                RecordOrCall::Record(record) if record.id == *id => Some((i, record)),
+                _ => None,
+            });
+        let written = if evaluator.merge_policy() == MergePolicy::FirstWins {
+            written.next()
+        } else {
+            written.max_by_key(|(i, _)| *i)
+        };
+        let Some((i, record)) = written else {
+            // only a call being evaluated could add the key
+            if let Some(indices) = pending {
+                doc.check_cycle(&indices, reference)?;
+            }
+            return Err(unresolved());
+        };
+        at = at.child(i, id);
+        if matches!(record.value, Value::ObjectWithCalls(_)) && !rest.is_empty() {
+            object = &record.value;
+            continue;
+        }
+        doc.check_cycle(&at.indices, reference)?;
+        let value = eval_record(evaluator, record, at, doc)?;
+        return index(value, rest).ok_or_else(unresolved);
+    }
+    Err(unresolved())
+}
+
+/// The value of `id` in the object of `entries` at `at`, merging the
+/// records written for it with those `added` by the record calls at their
+/// indices, in the order they appear.
+fn merged<E>(
+    evaluator: &mut E,
+    entries: &[RecordOrCall],
+    at: &Location,
+    id: &str,
+    added: Vec<(usize, Record)>,
+    reference: &Reference,
+    doc: &mut Document,
+) -> Result<Value, Error>
+where
+    E: Evaluator + ?Sized,
+{
+    let mut records = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        match entry {
+            RecordOrCall::Record(record) if record.id == id => {
+                let at = at.child(i, id);
+                doc.check_cycle(&at.indices, reference)?;
+                records.push((
+                    i,
+                    Record {
+                        id: record.id.clone(),
+                        value: eval_record(evaluator, record, at, doc)?,
+                        pos: record.pos,
+                        ..Record::default()
+                    },
+                ));
+            }
+            _ => (),
+        }
+    }
+    records.extend(added);
+    records.sort_by_key(|(i, _)| *i);
+
+    let policy = evaluator.merge_policy();
+    let mut object = Vec::new();
+    for (_, record) in records {
+        merge::insert(&mut object, record, policy).map_err(Error::DuplicateKey)?;
+    }
+    Ok(object.pop().map(|record| record.value).unwrap_or_default())
+}
+
+/// The value at `path` of keys in an evaluated `value`.
+fn index(value: Value, path: &[String]) -> Option<Value> {
+    let Some((first, rest)) = path.split_first() else {
+        return Some(value);
+    };
+    match value {
+        Value::Object(records) => {
+            let record = records.into_iter().rev().find(|r| r.id == *first)?;
+            index(record.value, rest)
+        }
+        Value::Typed(t) => index(*t.value, path),
+        _ => None,
+    }
+}
+
+/// Evaluates `value`, at `at` in the document if it is a record's value
+/// there rather than part of one.
+fn eval_value<E>(
+    evaluator: &mut E,
+    value: &Value,
+    at: Option<&Location>,
+    doc: &mut Document,
+) -> Result<Value, Error>
+where
+    E: Evaluator + ?Sized,
+{
+    match value {
+        Value::Call(ref call) => {
+            let evaluated = eval_args(evaluator, call, doc)?;
+            evaluator
+                .value_function_eval(&evaluated)
+                .map_err(|e| e.in_call('@', call))
+        }
+        Value::Reference(reference) => resolve(evaluator, reference, doc),
+        Value::ObjectWithCalls(object) => {
+            let mut obj = Vec::new();
+            let policy = evaluator.merge_policy();
+            for (i, entry) in object.iter().enumerate() {
+                match entry {
+                    RecordOrCall::Record(record) => {
+                        let value = match at {
+                            Some(at) => {
+                                eval_record(evaluator, record, at.child(i, &record.id), doc)?
                             }
+                            None => eval_value(evaluator, &record.value, None, doc)
+                                .map_err(|e| e.in_record(&record.id))?,
+                        };
+                        let record = Record {
+                            id: record.id.clone(),
+                            value,
+                            pos: record.pos,
+                            ..Record::default()
+                        };
+                        merge::insert(&mut obj, record, policy).map_err(Error::DuplicateKey)?;
+                    }
+                    RecordOrCall::Call(call) => {
+                        let indices = at.map(|at| {
+                            let mut indices = at.indices.clone();
+                            indices.push(i);
+                            indices
+                        });
+                        for rec in eval_record_call(evaluator, call, indices, doc)? {
+                            merge::insert(&mut obj, rec, policy).map_err(Error::DuplicateKey)?;
                         }
                     }
+                    RecordOrCall::Comment(_) => (),
                 }
-
-                Ok(Value::Object(obj))
             }
-            Value::Array(arr) => {
-                let mut values = Vec::new();
-                for val in arr {
-                    values.push(self.eval(val)?);
-                }
 
-                Ok(Value::Array(values))
-            }
-            Value::Typed(t) => {
-                let value = self.eval(&t.value)?;
-                Ok(Value::Typed(Typed {
-                    kind: t.kind.clone(),
-                    value: Box::new(value),
-                }))
-            }
-            Value::Float(_) | Value::Number(_) | Value::String(_) | Value::Object(_) => {
-                Ok(root.clone())
+            Ok(Value::Object(obj))
+        }
+        Value::Array(arr) => {
+            let mut values = Vec::new();
+            for element in arr {
+                values.push(Element {
+                    value: eval_value(evaluator, &element.value, None, doc)?,
+                    pos: element.pos,
+                    ..Default::default()
+                });
             }
+
+            Ok(Value::Array(values))
+        }
+        Value::Typed(t) => {
+            let value = eval_value(evaluator, &t.value, None, doc)?;
+            Ok(Value::Typed(Typed {
+                kind: t.kind.clone(),
+                value: Box::new(value),
+            }))
         }
+        Value::Error(pos) => Err(Error::Unparsed(*pos)),
+        Value::Null
+        | Value::Bool(_)
+        | Value::Float(_)
+        | Value::Number(_)
+        | Value::String(_)
+        | Value::Object(_) => Ok(value.clone()),
+    }
+}
+
+pub trait Evaluator {
+    fn value_function_eval(&mut self, call: &Call) -> Result<Value, Error>;
+    /// The records a record call adds to the object it is in.
+    fn record_function_eval(&mut self, call: &Call) -> Result<Vec<Record>, Error>;
+
+    /// How records sharing an id are merged when an object is evaluated,
+    /// including records produced by record calls.
+    fn merge_policy(&self) -> MergePolicy {
+        MergePolicy::default()
+    }
+
+    /// Evaluates the document `root`, in which `@ref` resolves.
+    fn eval(&mut self, root: &Value) -> Result<Value, Error> {
+        let mut doc = Document {
+            root,
+            values: HashMap::new(),
+            records: HashMap::new(),
+            evaluating: Vec::new(),
+        };
+        eval_value(self, root, Some(&Location::default()), &mut doc)
     }
 }
 
@@ -103,8 +650,8 @@ where
         fn value_function_eval(&mut self, call: &Call) -> Result<Value, Error> {
             (self.vf)(call)
         }
-        fn record_function_eval(&mut self, call: &Call) -> Result<Option<Record>, Error> {
-            (self.rf)(call)
+        fn record_function_eval(&mut self, call: &Call) -> Result<Vec<Record>, Error> {
+            (self.rf)(call).map(|record| record.into_iter().collect())
         }
     }
 
@@ -117,51 +664,111 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::{eval, Error, Record, Value};
-    use crate::ast::Call;
+    use super::{eval, single, Error, Record, Value};
+    use crate::ast::{Argument, Call};
+    use crate::convert::FromValue;
+    use crate::lexer::FilePos;
+    use crate::merge::Conflict;
     use std::collections::HashMap;
 
+    fn unknown(call: &Call) -> Error {
+        Error::InvalidFunction {
+            name: call.function.clone(),
+            suggestion: None,
+        }
+    }
+
     #[test]
     fn eval_literal() -> Result<(), Error> {
         let root = Value::Number(25);
-        type VFRet<'a> = Option<&'a dyn Fn(&Value) -> Result<Value, Error>>;
+        let result = eval(&root, &mut |c| Err(unknown(c)), &mut |c| Err(unknown(c)))?;
+        assert_eq!(root, result);
 
-        let result = eval(&root, &mut |_| Err(Error::InvalidFunction), &mut |_| {
-            Err(Error::InvalidFunction)
-        })?;
+        Ok(())
+    }
+
+    #[test]
+    fn eval_bool_and_null() -> Result<(), Error> {
+        let root = Value::array(vec![Value::Bool(true), Value::Null]);
+        let result = eval(&root, &mut |c| Err(unknown(c)), &mut |c| Err(unknown(c)))?;
         assert_eq!(root, result);
+        assert_eq!(serde_json::to_string(&result).unwrap(), "[true,null]");
 
         Ok(())
     }
 
     #[test]
     fn eval_call() -> Result<(), Error> {
-        let mut call = |v: &Value| return Ok(v.clone());
+        let mut call = |v: &Value| Ok(v.clone());
         let mut functions = HashMap::new();
         functions.insert("call".to_string(), &mut call);
         let value = Value::Number(2);
         let root = Value::Call(Call {
             function: "call".to_string(),
-            value: Box::new(value.clone()),
+            args: vec![value.clone().into()],
+            ..Default::default()
         });
         let result = eval(
             &root,
             &mut |c| {
                 functions
                     .get_mut(&c.function)
-                    .and_then(|a| Some(a(&c.value)))
-                    .ok_or(Error::InvalidFunction)?
+                    .map(|a| a(single(c)?))
+                    .ok_or_else(|| unknown(c))?
             },
-            &mut |_| Err(Error::InvalidFunction),
+            &mut |c| Err(unknown(c)),
         )?;
 
         assert_eq!(result, value);
         Ok(())
     }
 
+    #[test]
+    fn eval_call_arguments() -> Result<(), Error> {
+        let root = Value::Call(Call {
+            function: "join".to_string(),
+            args: vec![
+                Value::String("a".to_string()).into(),
+                Argument {
+                    name: Some("sep".to_string()),
+                    value: Value::Call(Call {
+                        function: "dash".to_string(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        });
+        let result = eval(
+            &root,
+            &mut |c| match c.function.as_str() {
+                "dash" => Ok(Value::String("-".to_string())),
+                "join" => {
+                    assert_eq!(c.named("sep"), Some(&Value::String("-".to_string())));
+                    assert!(
+                        matches!(single(c), Err(Error::UnexpectedArgument(name)) if name == "sep")
+                    );
+                    Ok(c.positional().next().cloned().unwrap_or_default())
+                }
+                _ => Err(unknown(c)),
+            },
+            &mut |c| Err(unknown(c)),
+        )?;
+        assert_eq!(result, Value::String("a".to_string()));
+
+        let empty = Call::default();
+        assert_eq!(
+            single(&empty).unwrap_err().to_string(),
+            "expected 1 argument, found 0"
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn eval_call_inside_object() -> Result<(), Error> {
-        let mut call = |v: &Value| return Ok(v.clone());
+        let mut call = |v: &Value| Ok(v.clone());
         let mut functions = HashMap::new();
         functions.insert("call".to_string(), &mut call);
         let value = Value::Number(2);
@@ -169,8 +776,10 @@ mod tests {
             id: "some".to_string(),
             value: Value::Call(Call {
                 function: "call".to_string(),
-                value: Box::new(value.clone()),
+                args: vec![value.clone().into()],
+                ..Default::default()
             }),
+            ..Default::default()
         }
         .into()]);
 
@@ -179,21 +788,124 @@ mod tests {
             &mut |c| {
                 functions
                     .get_mut(&c.function)
-                    .and_then(|a| Some(a(&c.value)))
-                    .ok_or(Error::InvalidFunction)?
+                    .map(|a| a(single(c)?))
+                    .ok_or_else(|| unknown(c))?
             },
-            &mut |_| Err(Error::InvalidFunction),
+            &mut |c| Err(unknown(c)),
         )?;
 
         assert_eq!(
             result,
             Value::Object(vec![Record {
                 id: "some".to_string(),
-                value: value
-            }
-            .into()])
+                value,
+                ..Default::default()
+            }])
         );
 
         Ok(())
     }
+
+    #[test]
+    fn eval_duplicate_from_record_call() {
+        let pos = |start| FilePos {
+            start,
+            end: start + 1,
+        };
+        let root = Value::ObjectWithCalls(vec![
+            Record {
+                id: "a".to_string(),
+                value: Value::Number(1),
+                pos: pos(0),
+                ..Default::default()
+            }
+            .into(),
+            Call {
+                function: "define".to_string(),
+                args: vec![Value::String("a".to_string()).into()],
+                ..Default::default()
+            }
+            .into(),
+        ]);
+
+        let result = eval(&root, &mut |c| Err(unknown(c)), &mut |c| {
+            Ok(Some(Record {
+                id: "a".to_string(),
+                value: single(c)?.clone(),
+                pos: pos(10),
+                ..Default::default()
+            }))
+        });
+
+        assert!(matches!(
+            result,
+            Err(Error::DuplicateKey(Conflict { id, first, second }))
+                if id == "a" && first == pos(0) && second == pos(10)
+        ));
+    }
+
+    fn parse(source: &str) -> Value {
+        let tokens = crate::lexer::tokenize(source).expect("valid tokens");
+        crate::parser::parse(&tokens).expect("valid document")
+    }
+
+    #[test]
+    fn eval_references() -> Result<(), Error> {
+        let root = parse(
+            "a = @ref b.c
+             b.c = @ref d.e
+             d = @ref f
+             #define \"f\"",
+        );
+        let mut defined = 0;
+        let result = eval(&root, &mut |c| Err(unknown(c)), &mut |c| {
+            defined += 1;
+            Ok(Some(Record {
+                id: String::from_value(single(c)?)?,
+                value: Value::Object(vec![Record {
+                    id: "e".to_string(),
+                    value: Value::Number(1),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }))
+        })?;
+        assert_eq!(defined, 1);
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"a":1,"b":{"c":1},"d":{"e":1},"f":{"e":1}}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn eval_reference_errors() {
+        let error = |source| {
+            eval(&parse(source), &mut |c| Err(unknown(c)), &mut |c| {
+                Err(unknown(c))
+            })
+            .unwrap_err()
+        };
+
+        let e = error("a = @ref a");
+        assert_eq!(e.to_string(), "reference cycle: a -> a");
+        assert!(matches!(
+            e,
+            Error::ReferenceCycle { reference, definition, .. }
+                if reference == FilePos { start: 4, end: 10 }
+                    && definition == FilePos { start: 0, end: 1 }
+        ));
+        assert_eq!(
+            error("a = { b = [@ref c] }\nc = @ref a.b").to_string(),
+            "reference cycle: a.b -> c -> a.b"
+        );
+        assert_eq!(
+            error("a = { b = 1 }\nc = @ref a.b.d").to_string(),
+            "unresolved reference to a.b.d"
+        );
+        assert_eq!(
+            error("a = [{ b = @ref b }]").to_string(),
+            "unresolved reference to b"
+        );
+    }
 }
@@ -1,20 +1,32 @@
 use crate::{
     ast::{Call, Record, Typed, Value},
     eval::{self, Error as EvalError},
-    parser, Version, VERSION,
+    lexer::FilePos,
+    merge::MergePolicy,
+    parser,
+    registry::Registry,
+    Version, VERSION,
 };
 use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::error::Error as StdError;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+mod collections;
+mod env;
+mod files;
+mod math;
+mod strings;
+
+pub use env::{Environment, ProcessEnvironment};
+pub use files::{FileSystem, NoFiles, OsFileSystem, Sandbox};
 
 #[derive(Debug)]
 enum Error {
     VersionMismatch(Version),
-    InvalidUrl(Value),
-    ExpectedObject(Value),
-
     InvalidEntry(String),
-    InvalidFunction(String),
-    InvalidData(Value),
     Eval(EvalError),
     Parse(parser::Error),
 }
@@ -29,15 +41,7 @@ impl fmt::Display for Error {
                     ver, VERSION
                 )
             }
-            Self::InvalidUrl(v) => {
-                write!(f, "invalid url \"{v:?}\"")
-            }
-            Self::ExpectedObject(v) => {
-                write!(f, "expected object, found {v:?}")
-            }
             Self::InvalidEntry(s) => write!(f, "invalid entry {s}"),
-            Self::InvalidFunction(s) => write!(f, "invalid function {s}"),
-            Self::InvalidData(d) => write!(f, "invalid data {d:?}"),
             Self::Eval(e) => write!(f, "eval error: {e}"),
             Self::Parse(e) => write!(f, "parsing error: {e}"),
         }
@@ -64,80 +68,176 @@ impl From<parser::Error> for Error {
     }
 }
 
-fn meta_lang(value: &Value) -> Result<Option<Record>, EvalError> {
-    match value {
-        Value::Float(version) => {
-            if *version != VERSION {
-                Err(Error::VersionMismatch(*version).into())
-            } else {
-                Ok(None)
-            }
-        }
-        _ => Err(Error::InvalidData(value.clone()).into()),
+/// Adds the position of the argument that failed to an error.
+fn argument(position: usize) -> impl Fn(EvalError) -> EvalError {
+    move |e| EvalError::Argument {
+        position,
+        pos: FilePos::default(),
+        error: Box::new(e),
     }
 }
 
-fn url(value: &Value) -> Result<Value, EvalError> {
-    match value {
-        Value::String(_) => Ok(Value::Typed(Typed {
-            value: Box::new(value.clone()),
-            kind: "std_url".to_string(),
-        })),
-        _ => Err(Error::InvalidUrl(value.clone()).into()),
+/// The arguments of a function taking any number of positional ones.
+fn variadic(call: &Call) -> Result<Vec<&Value>, EvalError> {
+    match call.args.iter().find_map(|arg| arg.name.as_ref()) {
+        Some(name) => Err(EvalError::UnexpectedArgument(name.clone())),
+        None => Ok(call.positional().collect()),
     }
 }
 
-#[derive(Default)]
+fn meta_lang(version: Version) -> Result<Option<Record>, EvalError> {
+    if version != VERSION {
+        return Err(Error::VersionMismatch(version).into());
+    }
+    Ok(None)
+}
+
+fn url(url: String) -> Result<Typed, EvalError> {
+    Ok(Typed {
+        value: Box::new(Value::String(url)),
+        kind: "std_url".to_string(),
+    })
+}
+
+/// Checks that `#meta-eval` lists only value and record functions.
+fn meta_eval(object: HashMap<String, Value>) -> Result<Option<Record>, EvalError> {
+    match object
+        .into_keys()
+        .filter(|k| k != "value" && k != "record")
+        .min()
+    {
+        Some(entry) => Err(Error::InvalidEntry(entry).into()),
+        None => Ok(None),
+    }
+}
+
+/// `#meta-lang`, `#meta-eval`, `@std.url`, which is also available as
+/// `@std_url`, the string, math and collection functions, and `@env` and
+/// `@env_required` reading the process environment.
+pub fn registry() -> Registry {
+    Registry::new()
+        .record("meta-lang", meta_lang)
+        .record("meta-eval", meta_eval)
+        .value("std_url", url)
+        .namespace("std", Registry::new().value("url", url))
+        .extend(strings::registry())
+        .extend(math::registry())
+        .extend(collections::registry())
+        .extend(env::registry(ProcessEnvironment))
+}
+
+/// Evaluates documents with the functions of a [`Registry`], with
+/// `#include` and `@import` loading other documents, and with `@read_text`,
+/// `@read_lines` and `@read_json` reading data files next to them.
 pub struct Evaluator {
-    value_functions: Vec<Record>,
-    record_functions: Vec<Record>,
+    registry: Registry,
+    files: Rc<RefCell<files::Files>>,
+    policy: MergePolicy,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new(registry())
+    }
 }
 
 impl Evaluator {
-    fn meta_eval(&mut self, value: &Value) -> Result<Option<Record>, EvalError> {
-        match value {
-            Value::Object(object) => {
-                for record in object {
-                    match record.id.as_str() {
-                        "value" => {
-                            if let Value::Object(ref data) = record.value {
-                                self.value_functions = data.clone();
-                            }
-                        }
-                        "record" => {
-                            if let Value::Object(ref data) = record.value {
-                                self.record_functions = data.clone();
-                            }
-                        }
-                        _ => return Err(Error::InvalidEntry(record.id.clone()).into()),
-                    };
-                }
-                Ok(None)
-            }
-            _ => Err(Error::ExpectedObject(value.clone()).into()),
+    /// Evaluates with the functions of `registry` and the file functions,
+    /// which those of `registry` replace.
+    pub fn new(registry: Registry) -> Self {
+        Self::with_files(|files| files.extend(registry))
+    }
+
+    /// Evaluates with the registry `build` makes of the file functions, to
+    /// namespace or leave out some of them.
+    ///
+    /// ```
+    /// use alt::goodies::{registry, Evaluator};
+    ///
+    /// let evaluator = Evaluator::with_files(|files| {
+    ///     registry().namespace("fs", files.without("read_json"))
+    /// });
+    /// ```
+    pub fn with_files(build: impl FnOnce(Registry) -> Registry) -> Self {
+        let files = Rc::default();
+        Self {
+            registry: build(files::registry(&files)),
+            files,
+            policy: MergePolicy::default(),
         }
     }
+
+    /// Evaluates `document`, read from `path`, loading the documents it
+    /// includes relative to it. [`eval::Evaluator::eval`] loads them relative
+    /// to the working directory.
+    pub fn eval_file(&mut self, path: &Path, document: &Value) -> Result<Value, EvalError> {
+        use eval::Evaluator as _;
+
+        self.files.borrow_mut().stack.push(files::normalize(path));
+        let value = self.eval(document);
+        self.files.borrow_mut().stack.pop();
+        value
+    }
+
+    /// Reads included documents from `system` instead of the process file
+    /// system, and data files too unless [`Evaluator::data_files`] is set.
+    #[must_use]
+    pub fn files(self, system: impl FileSystem + 'static) -> Self {
+        self.files.borrow_mut().system = Rc::new(system);
+        self
+    }
+
+    /// Reads the data files of `@read_text`, `@read_lines` and `@read_json`
+    /// from `system` instead of where documents are read from.
+    #[must_use]
+    pub fn data_files(self, system: impl FileSystem + 'static) -> Self {
+        self.files.borrow_mut().data = Some(Rc::new(system));
+        self
+    }
+
+    /// Adds `dir` to the directories searched for documents that are not
+    /// next to the one including them, in the order they are added.
+    #[must_use]
+    pub fn search_path(self, dir: impl Into<PathBuf>) -> Self {
+        self.files.borrow_mut().search_path.push(dir.into());
+        self
+    }
+
+    /// Merges records sharing an id with `policy`, in the documents it
+    /// includes too, instead of rejecting them.
+    #[must_use]
+    pub const fn with_merge_policy(mut self, policy: MergePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Reads environment variables from `environment` instead of the
+    /// process environment.
+    #[must_use]
+    pub fn environment(mut self, environment: impl Environment + 'static) -> Self {
+        self.registry = std::mem::take(&mut self.registry).extend(env::registry(environment));
+        self
+    }
 }
 
 impl eval::Evaluator for Evaluator {
-    fn record_function_eval(&mut self, call: &Call) -> Result<Option<Record>, EvalError> {
-        match call.function.as_str() {
-            "meta-lang" => meta_lang(&call.value),
-            "meta-eval" => self.meta_eval(&call.value),
-            _ => Err(Error::InvalidFunction("#".to_string() + &call.function).into()),
-        }
+    fn record_function_eval(&mut self, call: &Call) -> Result<Vec<Record>, EvalError> {
+        self.registry.record_function(&call.function)?(self, call)
     }
     fn value_function_eval(&mut self, call: &Call) -> Result<Value, EvalError> {
-        match call.function.as_str() {
-            "std_url" => url(&call.value),
-            _ => Err(Error::InvalidFunction("@".to_string() + &call.function).into()),
-        }
+        self.registry.value_function(&call.function)?(self, call)
+    }
+    fn merge_policy(&self) -> MergePolicy {
+        self.policy
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{lexer::tokenize, parser::parse};
+    use crate::{
+        lexer::{tokenize, FilePos},
+        parser::parse,
+    };
 
     use super::*;
 
@@ -146,11 +246,49 @@ mod tests {
         use eval::Evaluator as EvalEvaluator;
         let mut evaluator: Evaluator = Default::default();
 
-        let tokens = tokenize("#meta-eval {value = {std_url = @std_url \"localhost\"}}");
+        let tokens = tokenize("#meta-eval {value = {std_url = @std_url \"localhost\"}}")
+            .map_err(parser::Error::from)?;
         let parsed = parse(&tokens)?;
         let evaluated = evaluator.eval(&parsed)?;
         println!("{evaluated:?}");
 
         Ok(())
     }
+
+    #[test]
+    fn errors_point_at_calls() -> Result<(), Error> {
+        use eval::Evaluator as EvalEvaluator;
+        let mut evaluator: Evaluator = Default::default();
+
+        let tokens = tokenize("a = 1\nb = [@std_url 2]").map_err(parser::Error::from)?;
+        let error = evaluator.eval(&parse(&tokens)?).unwrap_err();
+        // the argument that failed, rather than the call
+        assert_eq!(error.pos(), Some(FilePos { start: 20, end: 21 }));
+        assert!(matches!(
+            error,
+            EvalError::Call {
+                pos: FilePos { start: 11, end: 19 },
+                ..
+            }
+        ));
+        assert!(matches!(
+            error.root(),
+            EvalError::Argument { position: 1, .. }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "argument 1: expected string, found number in @std_url"
+        );
+
+        let tokens = tokenize("x = @starts_with(\n    \"abc\",\n    [\"a\"]\n)")
+            .map_err(parser::Error::from)?;
+        let error = evaluator.eval(&parse(&tokens)?).unwrap_err();
+        assert_eq!(error.pos(), Some(FilePos { start: 33, end: 34 }));
+
+        let tokens = tokenize("#meta-lang 1.0\n#nope 1").map_err(parser::Error::from)?;
+        let error = evaluator.eval(&parse(&tokens)?).unwrap_err();
+        assert_eq!(error.pos(), Some(FilePos { start: 15, end: 20 }));
+
+        Ok(())
+    }
 }
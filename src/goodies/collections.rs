@@ -0,0 +1,553 @@
+use super::{argument, strings, variadic};
+use crate::{
+    ast::{Call, Record, Value},
+    convert::{FromValue, IntoValue},
+    eval::Error as EvalError,
+    merge::{self, MergePolicy},
+    registry::Registry,
+};
+use core::fmt;
+use std::{cmp::Ordering, error::Error as StdError};
+
+/// The most elements `@range` makes.
+const MAX_RANGE: usize = 1_000_000;
+
+#[derive(Debug)]
+enum Error {
+    /// A path passed to `@get` that leads nowhere.
+    MissingKey(String),
+    /// Arrays passed to `@zip` with the given lengths.
+    LengthMismatch(usize, usize),
+    DuplicateKey(String),
+    ZeroStep,
+    RangeTooLarge,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingKey(path) => write!(f, "no value at {path}"),
+            Self::LengthMismatch(a, b) => {
+                write!(f, "arrays of different lengths, {a} and {b}")
+            }
+            Self::DuplicateKey(key) => write!(f, "duplicate key {key}"),
+            Self::ZeroStep => write!(f, "range step is 0"),
+            Self::RangeTooLarge => write!(f, "range has more than {MAX_RANGE} elements"),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<Error> for EvalError {
+    fn from(value: Error) -> Self {
+        EvalError::Eval(Box::new(value))
+    }
+}
+
+/// The records of an object argument, in order.
+struct Object(Vec<Record>);
+
+impl FromValue for Object {
+    fn from_value(value: &Value) -> Result<Self, EvalError> {
+        match value {
+            Value::Object(records) => Ok(Self(records.clone())),
+            Value::Typed(t) => Self::from_value(&t.value),
+            _ => Err(EvalError::Type {
+                expected: "object",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl IntoValue for Object {
+    fn into_value(self) -> Value {
+        Value::Object(self.0)
+    }
+}
+
+/// An argument that may be left out, unlike `Option<Value>` which also
+/// treats `null` as left out.
+struct Fallback(Option<Value>);
+
+impl FromValue for Fallback {
+    fn from_value(value: &Value) -> Result<Self, EvalError> {
+        Ok(Self(Some(value.clone())))
+    }
+
+    fn missing() -> Option<Self> {
+        Some(Self(None))
+    }
+}
+
+fn record(id: String, value: Value) -> Record {
+    Record {
+        id,
+        value,
+        ..Default::default()
+    }
+}
+
+/// The number of characters of a string, elements of an array or records of
+/// an object.
+fn len(value: Value) -> Result<usize, EvalError> {
+    match value {
+        Value::String(s) => Ok(s.chars().count()),
+        Value::Array(values) => Ok(values.len()),
+        Value::Object(records) => Ok(records.len()),
+        Value::Typed(t) => len(*t.value),
+        _ => Err(argument(1)(EvalError::Type {
+            expected: "string, array or object",
+            found: value.type_name(),
+        })),
+    }
+}
+
+/// Joins arrays if the first argument is one, strings otherwise.
+fn concat(call: &Call) -> Result<Value, EvalError> {
+    let values = variadic(call)?;
+    if !matches!(values.first(), Some(Value::Array(_))) {
+        return strings::concat(call);
+    }
+    let mut out = Vec::new();
+    for (i, value) in values.into_iter().enumerate() {
+        out.extend(Vec::<Value>::from_value(value).map_err(argument(i + 1))?);
+    }
+    Ok(Value::array(out))
+}
+
+/// Flattens nested arrays `depth` levels deep, one by default.
+fn flatten(values: Vec<Value>, depth: Option<usize>) -> Result<Vec<Value>, EvalError> {
+    fn flatten_into(out: &mut Vec<Value>, values: Vec<Value>, depth: usize) {
+        for value in values {
+            match value {
+                Value::Array(inner) if depth > 0 => {
+                    flatten_into(out, inner.into_iter().map(|e| e.value).collect(), depth - 1);
+                }
+                value => out.push(value),
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    flatten_into(&mut out, values, depth.unwrap_or(1));
+    Ok(out)
+}
+
+/// Drops values equal to an earlier one.
+fn unique(values: Vec<Value>) -> Result<Vec<Value>, EvalError> {
+    let mut out = Vec::<Value>::new();
+    for value in values {
+        if !out.contains(&value) {
+            out.push(value);
+        }
+    }
+    Ok(out)
+}
+
+/// Sorts an array of numbers and floats, of strings or of booleans.
+fn sort(mut values: Vec<Value>) -> Result<Vec<Value>, EvalError> {
+    fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+        match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Some(a.cmp(b)),
+            (Value::Number(_) | Value::Float(_), Value::Number(_) | Value::Float(_)) => Some(
+                f64::from_value(a)
+                    .ok()?
+                    .total_cmp(&f64::from_value(b).ok()?),
+            ),
+            (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+
+    if let Some(first) = values.first() {
+        let expected = match first {
+            Value::Number(_) | Value::Float(_) => "number or float",
+            Value::String(_) => "string",
+            Value::Bool(_) => "boolean",
+            _ => "number, float, string or boolean",
+        };
+        if let Some(value) = values.iter().find(|v| compare(first, v).is_none()) {
+            return Err(argument(1)(EvalError::Type {
+                expected,
+                found: value.type_name(),
+            }));
+        }
+    }
+    values.sort_by(|a, b| compare(a, b).unwrap_or(Ordering::Equal));
+    Ok(values)
+}
+
+fn reverse(value: Value) -> Result<Value, EvalError> {
+    match value {
+        Value::String(s) => Ok(Value::String(s.chars().rev().collect())),
+        Value::Array(mut values) => {
+            values.reverse();
+            Ok(Value::Array(values))
+        }
+        Value::Typed(t) => reverse(*t.value),
+        _ => Err(argument(1)(EvalError::Type {
+            expected: "string or array",
+            found: value.type_name(),
+        })),
+    }
+}
+
+/// `@range 3` is `[0 1 2]`, `@range(1, 7, 2)` is `[1 3 5]`.
+fn range(start: i64, end: Option<i64>, step: Option<i64>) -> Result<Vec<i64>, EvalError> {
+    let (start, end) = match end {
+        Some(end) => (start, end),
+        None => (0, start),
+    };
+    let step = step.unwrap_or(1);
+    if step == 0 {
+        return Err(Error::ZeroStep.into());
+    }
+    let mut out = Vec::new();
+    let mut n = start;
+    while (step > 0 && n < end) || (step < 0 && n > end) {
+        if out.len() == MAX_RANGE {
+            return Err(Error::RangeTooLarge.into());
+        }
+        out.push(n);
+        match n.checked_add(step) {
+            Some(next) => n = next,
+            None => break,
+        }
+    }
+    Ok(out)
+}
+
+/// Merges objects, replacing or, with `deep = true`, merging records with
+/// the same key. A single array argument is a list of objects to merge.
+fn merge(call: &Call) -> Result<Value, EvalError> {
+    let deep = match call.named("deep") {
+        Some(deep) => bool::from_value(deep)?,
+        None => false,
+    };
+    if let Some(name) = call
+        .args
+        .iter()
+        .filter_map(|arg| arg.name.as_ref())
+        .find(|name| *name != "deep")
+    {
+        return Err(EvalError::UnexpectedArgument(name.clone()));
+    }
+    let objects = match call.positional().collect::<Vec<_>>().as_slice() {
+        [Value::Array(values)] => values.iter().map(|e| &e.value).collect(),
+        values => values.to_vec(),
+    };
+    let policy = if deep {
+        MergePolicy::DeepMerge
+    } else {
+        MergePolicy::LastWins
+    };
+
+    let mut records = Vec::new();
+    for (i, object) in objects.into_iter().enumerate() {
+        for record in Object::from_value(object).map_err(argument(i + 1))?.0 {
+            // Neither policy reports conflicts.
+            let _ = merge::insert(&mut records, record, policy);
+        }
+    }
+    Ok(Value::Object(records))
+}
+
+/// The steps of a path given as `"a.b.0"` or `["a" "b" 0]`.
+fn path(value: &Value) -> Result<Vec<String>, EvalError> {
+    match value {
+        Value::String(s) => Ok(s.split('.').map(str::to_string).collect()),
+        Value::Array(steps) => steps.iter().map(|e| strings::text(&e.value)).collect(),
+        Value::Typed(t) => path(&t.value),
+        _ => Err(EvalError::Type {
+            expected: "string or array",
+            found: value.type_name(),
+        }),
+    }
+}
+
+/// Looks up a path of keys and array indices, returning `default` if any
+/// step is missing.
+fn get(value: Value, steps: Value, default: Fallback) -> Result<Value, EvalError> {
+    let steps = path(&steps).map_err(argument(2))?;
+    let mut current = &value;
+    for (i, step) in steps.iter().enumerate() {
+        let next = match current {
+            Value::Object(records) => records.iter().find(|r| &r.id == step).map(|r| &r.value),
+            Value::Array(values) => step
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| values.get(i))
+                .map(|e| &e.value),
+            _ => None,
+        };
+        current = match (next, &default.0) {
+            (Some(next), _) => next,
+            (None, Some(default)) => return Ok(default.clone()),
+            (None, None) => return Err(Error::MissingKey(steps[..=i].join(".")).into()),
+        };
+    }
+    Ok(current.clone())
+}
+
+/// The records of `object` whose keys are, or with `keep` false are not, in
+/// `keys`.
+fn select(object: Object, keys: &[String], keep: bool) -> Object {
+    Object(
+        object
+            .0
+            .into_iter()
+            .filter(|r| keys.contains(&r.id) == keep)
+            .collect(),
+    )
+}
+
+/// `@zip([1 2], ["a" "b"])` is `[[1 "a"] [2 "b"]]`.
+fn zip(call: &Call) -> Result<Value, EvalError> {
+    let arrays = variadic(call)?
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| Vec::<Value>::from_value(value).map_err(argument(i + 1)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = arrays.first().map_or(0, Vec::len);
+    if let Some(other) = arrays.iter().find(|a| a.len() != len) {
+        return Err(Error::LengthMismatch(len, other.len()).into());
+    }
+    Ok(Value::array((0..len).map(|i| {
+        Value::array(arrays.iter().map(|a| a[i].clone()))
+    })))
+}
+
+/// Makes an object of `[key value]` pairs.
+fn to_object(pairs: Vec<(String, Value)>) -> Result<Object, EvalError> {
+    let mut records = Vec::<Record>::new();
+    for (id, value) in pairs {
+        if records.iter().any(|r| r.id == id) {
+            return Err(Error::DuplicateKey(id).into());
+        }
+        records.push(record(id, value));
+    }
+    Ok(Object(records))
+}
+
+/// `@len`, `@concat`, `@flatten`, `@unique`, `@sort`, `@reverse`, `@range`,
+/// `@keys`, `@values`, `@merge`, `@get`, `@pick`, `@omit`, `@zip` and
+/// `@to_object`. `@len`, `@concat` and `@reverse` also work on strings.
+pub(super) fn registry() -> Registry {
+    Registry::new()
+        .value("len", len)
+        .value_raw("concat", concat)
+        .value("flatten", flatten)
+        .value("unique", unique)
+        .value("sort", sort)
+        .value("reverse", reverse)
+        .value("range", range)
+        .value("keys", |object: Object| {
+            Ok(object.0.into_iter().map(|r| r.id).collect::<Vec<_>>())
+        })
+        .value("values", |object: Object| {
+            Ok(object.0.into_iter().map(|r| r.value).collect::<Vec<_>>())
+        })
+        .value_raw("merge", merge)
+        .value("get", get)
+        .value("pick", |object: Object, keys: Vec<String>| {
+            Ok(select(object, &keys, true))
+        })
+        .value("omit", |object: Object, keys: Vec<String>| {
+            Ok(select(object, &keys, false))
+        })
+        .value_raw("zip", zip)
+        .value("to_object", to_object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize, parser::parse};
+
+    /// Evaluates `x = {source}` and returns `x`.
+    fn eval(source: &str) -> Result<Value, EvalError> {
+        let tokens = tokenize(&format!("x = {source}")).expect("valid tokens");
+        match registry().eval(&parse(&tokens).expect("valid document"))? {
+            Value::Object(mut records) => Ok(records.remove(0).value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        eval(source).unwrap_err().root().to_string()
+    }
+
+    fn numbers(n: &[i64]) -> Value {
+        n.to_vec().into_value()
+    }
+
+    fn object(records: &[(&str, Value)]) -> Value {
+        Value::Object(
+            records
+                .iter()
+                .map(|(id, value)| record(id.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn arrays() -> Result<(), EvalError> {
+        assert_eq!(eval("@len [1 2 3]")?, Value::Number(3));
+        assert_eq!(eval("@len {a = 1}")?, Value::Number(1));
+        // characters rather than the 6 bytes
+        assert_eq!(eval("@len \"héllo\"")?, Value::Number(5));
+        assert_eq!(eval("@concat([1], [], [2 3])")?, numbers(&[1, 2, 3]));
+        assert_eq!(eval("@concat(\"a\", 1)")?, "a1".into_value());
+        assert_eq!(
+            eval("@concat(\"a\", 1, 2.5, false)")?,
+            "a12.5false".into_value()
+        );
+        assert_eq!(eval("@concat()")?, "".into_value());
+        assert_eq!(
+            eval("@flatten [[1 [2]] 3]")?,
+            Value::array(vec![Value::Number(1), numbers(&[2]), Value::Number(3)])
+        );
+        assert_eq!(eval("@flatten([[1 [2]] 3], 2)")?, numbers(&[1, 2, 3]));
+        assert_eq!(eval("@unique [3 1 3 2 1]")?, numbers(&[3, 1, 2]));
+        assert_eq!(
+            eval("@sort [3 1.5 2]")?,
+            Value::array(vec![Value::Float(1.5), Value::Number(2), Value::Number(3)])
+        );
+        assert_eq!(eval("@sort [\"b\" \"a\"]")?, vec!["a", "b"].into_value());
+        assert_eq!(eval("@reverse [1 2 3]")?, numbers(&[3, 2, 1]));
+        assert_eq!(eval("@reverse \"abc\"")?, "cba".into_value());
+        assert_eq!(eval("@range 3")?, numbers(&[0, 1, 2]));
+        assert_eq!(eval("@range(1, 7, 2)")?, numbers(&[1, 3, 5]));
+        assert_eq!(eval("@range(3, 0, -1)")?, numbers(&[3, 2, 1]));
+        assert_eq!(eval("@range(2, 1)")?, numbers(&[]));
+        assert_eq!(
+            eval("@zip([1 2], [\"a\" \"b\"])")?,
+            Value::array(vec![(1i64, "a").into_value(), (2i64, "b").into_value()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn objects() -> Result<(), EvalError> {
+        let server = "{host = \"localhost\"; port = 80; tls = {on = false}}";
+        assert_eq!(
+            eval(&format!("@keys {server}"))?,
+            vec!["host", "port", "tls"].into_value()
+        );
+        assert_eq!(eval("@values {a = 1; b = 2}")?, numbers(&[1, 2]));
+        assert_eq!(
+            eval(&format!("@get({server}, \"tls.on\")"))?,
+            Value::Bool(false)
+        );
+        assert_eq!(
+            eval("@get({a = [{b = 1}]}, [\"a\" 0 \"b\"])")?,
+            Value::Number(1)
+        );
+        assert_eq!(
+            eval(&format!("@get({server}, \"tls.cert\", null)"))?,
+            Value::Null
+        );
+        assert_eq!(
+            eval(&format!("@pick({server}, [\"port\" \"host\"])"))?,
+            object(&[
+                ("host", "localhost".into_value()),
+                ("port", Value::Number(80))
+            ])
+        );
+        assert_eq!(
+            eval(&format!("@omit({server}, [\"host\" \"tls\"])"))?,
+            object(&[("port", Value::Number(80))])
+        );
+        assert_eq!(
+            eval("@to_object @zip([\"a\" \"b\"], [1 2])")?,
+            object(&[("a", Value::Number(1)), ("b", Value::Number(2))])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn merges() -> Result<(), EvalError> {
+        let a = "{x = {y = 1}; z = 1}";
+        let b = "{x = {w = 2}}";
+        assert_eq!(
+            eval(&format!("@merge({a}, {b})"))?,
+            object(&[
+                ("x", object(&[("w", Value::Number(2))])),
+                ("z", Value::Number(1))
+            ])
+        );
+        assert_eq!(
+            eval(&format!("@merge({a}, {b}, deep = true)"))?,
+            object(&[
+                (
+                    "x",
+                    object(&[("y", Value::Number(1)), ("w", Value::Number(2))])
+                ),
+                ("z", Value::Number(1))
+            ])
+        );
+        assert_eq!(
+            eval(&format!("@merge [{a} {{z = 2}}]"))?,
+            object(&[
+                ("x", object(&[("y", Value::Number(1))])),
+                ("z", Value::Number(2))
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("@len 1"),
+            "argument 1: expected string, array or object, found number"
+        );
+        assert_eq!(
+            error("@concat(\"a\", null)"),
+            "argument 2: expected string, number or boolean, found null"
+        );
+        assert_eq!(
+            error("@concat([1], \"a\")"),
+            "argument 2: expected array, found string"
+        );
+        assert_eq!(
+            error("@sort [1 \"a\"]"),
+            "argument 1: expected number or float, found string"
+        );
+        assert_eq!(
+            error("@range(0, 5, 0)"),
+            "function eval error: range step is 0"
+        );
+        assert_eq!(
+            error("@range 2000000"),
+            "function eval error: range has more than 1000000 elements"
+        );
+        assert_eq!(
+            error("@get({a = {b = 1}}, \"a.c.d\")"),
+            "function eval error: no value at a.c"
+        );
+        assert_eq!(
+            error("@zip([1], [1 2])"),
+            "function eval error: arrays of different lengths, 1 and 2"
+        );
+        assert_eq!(
+            error("@to_object [[\"a\" 1] [\"a\" 2]]"),
+            "function eval error: duplicate key a"
+        );
+        assert_eq!(
+            error("@to_object [[\"a\" 1 2]]"),
+            "argument 1: expected array of 2 values, found array"
+        );
+        assert_eq!(
+            error("@merge({}, 1)"),
+            "argument 2: expected object, found number"
+        );
+        assert_eq!(
+            error("@merge({}, shallow = true)"),
+            "unexpected argument shallow"
+        );
+    }
+}
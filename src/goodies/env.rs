@@ -0,0 +1,95 @@
+use crate::{ast::Value, eval::Error as EvalError, registry::Registry};
+use std::{collections::HashMap, rc::Rc};
+
+/// Where `@env` and `@env_required` read variables from.
+pub trait Environment {
+    fn var(&self, name: &str) -> Option<String>;
+}
+
+/// The environment of the running process.
+pub struct ProcessEnvironment;
+
+impl Environment for ProcessEnvironment {
+    fn var(&self, name: &str) -> Option<String> {
+        std::env::var(name).ok()
+    }
+}
+
+impl Environment for HashMap<String, String> {
+    fn var(&self, name: &str) -> Option<String> {
+        self.get(name).cloned()
+    }
+}
+
+/// `@env(name, default)`, which is `default` or `null` for an unset
+/// variable, and `@env_required name`, which is an error for one.
+pub(super) fn registry(environment: impl Environment + 'static) -> Registry {
+    let environment = Rc::new(environment);
+    let required = Rc::clone(&environment);
+    Registry::new()
+        .value("env", move |name: String, default: Option<Value>| {
+            Ok(environment
+                .var(&name)
+                .map_or_else(|| default.unwrap_or_default(), Value::String))
+        })
+        .value("env_required", move |name: String| {
+            required
+                .var(&name)
+                .ok_or(EvalError::MissingVariable { name, record: None })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diagnostics::Diagnostic, eval::Evaluator, goodies, lexer::tokenize, parser::parse,
+    };
+
+    fn environment() -> HashMap<String, String> {
+        HashMap::from([("HOST".to_string(), "example.com".to_string())])
+    }
+
+    fn eval(source: &str) -> Result<Value, EvalError> {
+        let tokens = tokenize(source).expect("valid tokens");
+        goodies::Evaluator::default()
+            .environment(environment())
+            .eval(&parse(&tokens).expect("valid document"))
+    }
+
+    #[test]
+    fn variables() -> Result<(), EvalError> {
+        let value = eval(
+            "host = @env \"HOST\"
+             port = @env(\"PORT\", 8080)
+             user = @env \"USER_THAT_IS_NOT_SET\"
+             url = @format(\"https://{}\", @env_required \"HOST\")",
+        )?;
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"host":"example.com","port":8080,"user":null,"url":"https://example.com"}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn required() {
+        let source = "database = {\n  url = [@env_required \"DB_URL\"]\n}";
+        let error = eval(source).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "environment variable DB_URL is not set, required by database.url in @env_required"
+        );
+        assert_eq!(
+            Diagnostic::from(&error).render(source, "test.alt"),
+            "error: environment variable DB_URL is not set, required by database.url
+ --> test.alt:2:10
+  |
+2 |   url = [@env_required \"DB_URL\"]
+  |          ^^^^^^^^^^^^^ in @env_required
+  |
+  = help: set DB_URL, or use @env with a default
+"
+        );
+    }
+}
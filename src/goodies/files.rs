@@ -0,0 +1,710 @@
+use super::argument;
+use crate::{
+    ast::{Call, Record, Value},
+    convert::FromValue,
+    eval::{self, Error as EvalError},
+    lexer, parser,
+    registry::Registry,
+};
+use core::fmt;
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error as StdError,
+    io,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+/// Where `#include` and `@import` read documents from, and `@read_text`,
+/// `@read_json` and `@read_lines` read data files from, see
+/// [`super::Evaluator::files`] and [`super::Evaluator::data_files`].
+pub trait FileSystem {
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+}
+
+/// The file system of the running process.
+pub struct OsFileSystem;
+
+impl FileSystem for OsFileSystem {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Files kept in memory, by path.
+impl FileSystem for HashMap<PathBuf, String> {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        self.get(path)
+            .cloned()
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+}
+
+/// A file system without files, which disables reading them.
+pub struct NoFiles;
+
+impl FileSystem for NoFiles {
+    fn read_to_string(&self, _: &Path) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "reading files is disabled",
+        ))
+    }
+}
+
+/// Only the files of `files` below the directory `root`.
+pub struct Sandbox<F> {
+    pub root: PathBuf,
+    pub files: F,
+}
+
+impl<F: FileSystem> FileSystem for Sandbox<F> {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let (normal, root) = (normalize(path), normalize(&self.root));
+        // `..` can only be left at the start, which leaves any root
+        let inside = normal.starts_with(&root)
+            && normal.has_root() == root.has_root()
+            && !normal.components().any(|c| c == Component::ParentDir);
+        if inside {
+            self.files.read_to_string(path)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("outside of {}", self.root.display()),
+            ))
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Error {
+    /// A file that is in none of the searched places.
+    NotFound {
+        name: String,
+        searched: Vec<PathBuf>,
+    },
+    Read {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// A file that includes itself, with the files in between.
+    Cycle(Vec<PathBuf>),
+    Json {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound { name, searched } => {
+                write!(f, "{name} not found, searched ")?;
+                let searched = searched
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "{}", searched.join(", "))
+            }
+            Self::Read { path, error } => write!(f, "cannot read {}: {error}", path.display()),
+            Self::Cycle(chain) => {
+                let chain = chain
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "include cycle: {}", chain.join(" -> "))
+            }
+            Self::Json { path, error } => {
+                write!(f, "invalid JSON in {}: {error}", path.display())
+            }
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<Error> for EvalError {
+    fn from(value: Error) -> Self {
+        EvalError::Eval(Box::new(value))
+    }
+}
+
+/// `path` with `.` and `a/..` steps removed, so that one file is only
+/// reached by one path.
+pub(super) fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                out.pop();
+            }
+            component => out.push(component),
+        }
+    }
+    out
+}
+
+/// The documents loaded by `#include` and `@import`, and the data files
+/// read next to them.
+pub(super) struct Files {
+    pub(super) system: Rc<dyn FileSystem>,
+    /// Where data files are read from, when not from `system`.
+    pub(super) data: Option<Rc<dyn FileSystem>>,
+    pub(super) search_path: Vec<PathBuf>,
+    /// The documents being evaluated, the innermost last.
+    pub(super) stack: Vec<PathBuf>,
+}
+
+impl Default for Files {
+    fn default() -> Self {
+        Self {
+            system: Rc::new(OsFileSystem),
+            data: None,
+            search_path: Vec::new(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl Files {
+    /// Reads the document `name`.
+    fn document(&self, name: &str) -> Result<(PathBuf, String), EvalError> {
+        self.resolve(&*self.system, name)
+    }
+
+    /// Reads the data file `name`.
+    fn data(&self, name: &str) -> Result<(PathBuf, String), EvalError> {
+        self.resolve(self.data.as_deref().unwrap_or(&*self.system), name)
+    }
+
+    /// Finds `name` in `system` next to the innermost document, or in the
+    /// search path, and reads it.
+    fn resolve(&self, system: &dyn FileSystem, name: &str) -> Result<(PathBuf, String), EvalError> {
+        let here = self
+            .stack
+            .last()
+            .and_then(|p| p.parent())
+            .unwrap_or(Path::new(""));
+        let candidates = std::iter::once(here)
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|dir| normalize(&dir.join(name)))
+            .collect::<Vec<_>>();
+        for path in &candidates {
+            match system.read_to_string(path) {
+                Ok(source) => return Ok((path.clone(), source)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(error) => {
+                    return Err(Error::Read {
+                        path: path.clone(),
+                        error,
+                    }
+                    .into())
+                }
+            }
+        }
+        Err(Error::NotFound {
+            name: name.to_string(),
+            searched: candidates,
+        }
+        .into())
+    }
+}
+
+/// Parses the document `source` read from `path` and evaluates it with
+/// `evaluator`, with documents it loads resolved relative to it.
+fn load(
+    files: &RefCell<Files>,
+    evaluator: &mut dyn eval::Evaluator,
+    path: PathBuf,
+    source: String,
+) -> Result<Value, EvalError> {
+    let cycle = {
+        let stack = &files.borrow().stack;
+        stack
+            .iter()
+            .position(|p| *p == path)
+            .map(|i| stack[i..].to_vec())
+    };
+    if let Some(mut chain) = cycle {
+        chain.push(path);
+        return Err(Error::Cycle(chain).into());
+    }
+
+    let document = match lexer::tokenize(&source) {
+        Ok(tokens) => {
+            let (document, errors) = parser::parse_recovering(&tokens, evaluator.merge_policy());
+            if errors.is_empty() {
+                Ok(document)
+            } else {
+                Err(EvalError::Syntax(errors))
+            }
+        }
+        Err(e) => Err(EvalError::Syntax(vec![e.into()])),
+    };
+
+    files.borrow_mut().stack.push(path.clone());
+    let value = document.and_then(|document| evaluator.eval(&document));
+    files.borrow_mut().stack.pop();
+    value.map_err(|error| EvalError::Document {
+        path: path.display().to_string(),
+        source,
+        error: Box::new(error),
+    })
+}
+
+/// `@import "db.alt"`, the value of the document `db.alt`.
+fn import(
+    files: &RefCell<Files>,
+    evaluator: &mut dyn eval::Evaluator,
+    call: &Call,
+) -> Result<Value, EvalError> {
+    let name = String::from_value(eval::single(call)?).map_err(argument(1))?;
+    let (path, source) = files.borrow().document(&name)?;
+    load(files, evaluator, path, source)
+}
+
+/// `#include "common.alt"`, the records of the document `common.alt`.
+fn include(
+    files: &RefCell<Files>,
+    evaluator: &mut dyn eval::Evaluator,
+    call: &Call,
+) -> Result<Vec<Record>, EvalError> {
+    // documents evaluate to objects, unless the evaluator is odd
+    let records = match import(files, evaluator, call)? {
+        Value::Object(records) => records,
+        value => {
+            return Err(EvalError::Type {
+                expected: "object",
+                found: value.type_name(),
+            })
+        }
+    };
+    // the records take the position of the include in this document
+    Ok(records
+        .into_iter()
+        .map(|r| Record {
+            pos: Default::default(),
+            ..r
+        })
+        .collect())
+}
+
+/// A function of the name of a data file, which it reads and passes to
+/// `f` with the path it was found at.
+fn reader<T>(
+    files: &Rc<RefCell<Files>>,
+    f: fn(PathBuf, String) -> Result<T, EvalError>,
+) -> impl FnMut(String) -> Result<T, EvalError> {
+    let files = Rc::clone(files);
+    move |name| {
+        let (path, text) = files.borrow().data(&name)?;
+        f(path, text)
+    }
+}
+
+/// A JSON document as an Alt value, with object keys in the order they
+/// were written. Numbers that do not fit a number become floats, and of a
+/// key written twice the last value is kept.
+struct Json(Value);
+
+impl<'de> Deserialize<'de> for Json {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonVisitor).map(Json)
+    }
+}
+
+struct JsonVisitor;
+
+impl<'de> Visitor<'de> for JsonVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::Number(n))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<Value, E> {
+        Ok(i64::try_from(n).map_or(Value::Float(n as f64), Value::Number))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<Value, E> {
+        Ok(Value::Float(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(Json(value)) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut records: Vec<Record> = Vec::new();
+        while let Some((id, Json(value))) = map.next_entry::<String, Json>()? {
+            match records.iter_mut().find(|r| r.id == id) {
+                Some(record) => record.value = value,
+                None => records.push(Record {
+                    id,
+                    value,
+                    ..Default::default()
+                }),
+            }
+        }
+        Ok(Value::Object(records))
+    }
+}
+
+fn read_json(path: PathBuf, text: String) -> Result<Value, EvalError> {
+    match serde_json::from_str(&text) {
+        Ok(Json(value)) => Ok(value),
+        Err(error) => Err(Error::Json { path, error }.into()),
+    }
+}
+
+/// `#include` and `@import`, loading documents through `files`, and
+/// `@read_text`, `@read_lines`, which leaves out line endings, and
+/// `@read_json` reading data files through it.
+pub(super) fn registry(files: &Rc<RefCell<Files>>) -> Registry {
+    let include = {
+        let files = Rc::clone(files);
+        move |evaluator: &mut dyn eval::Evaluator, call: &Call| include(&files, evaluator, call)
+    };
+    let import = {
+        let files = Rc::clone(files);
+        move |evaluator: &mut dyn eval::Evaluator, call: &Call| import(&files, evaluator, call)
+    };
+    Registry::new()
+        .record_with("include", include)
+        .value_with("import", import)
+        .value("read_text", reader(files, |_, text| Ok(text)))
+        .value(
+            "read_lines",
+            reader(files, |_, text| {
+                Ok(text.lines().map(str::to_string).collect::<Vec<_>>())
+            }),
+        )
+        .value("read_json", reader(files, read_json))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diagnostics::render_eval, goodies::Evaluator, lexer::tokenize, parser::parse};
+
+    fn files(files: &[(&str, &str)]) -> HashMap<PathBuf, String> {
+        files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect()
+    }
+
+    fn memory(files: &HashMap<PathBuf, String>) -> Evaluator {
+        Evaluator::default().files(files.clone())
+    }
+
+    /// Evaluates the document at `path` and renders the result as JSON, or
+    /// its error as a diagnostic.
+    fn run(mut evaluator: Evaluator, files: &HashMap<PathBuf, String>, path: &str) -> String {
+        let source = &files[Path::new(path)];
+        let document = parse(&tokenize(source).expect("valid tokens")).expect("valid document");
+        match evaluator.eval_file(Path::new(path), &document) {
+            Ok(value) => serde_json::to_string(&value).unwrap(),
+            Err(e) => render_eval(&e, source, path),
+        }
+    }
+
+    #[test]
+    fn includes_and_imports() {
+        let files = files(&[
+            (
+                "conf/main.alt",
+                "#include \"common.alt\"\ndb = @import \"db/db.alt\"",
+            ),
+            ("conf/common.alt", "name = \"app\""),
+            ("conf/db/db.alt", "#include \"../common.alt\"\nport = 5432"),
+        ]);
+        assert_eq!(
+            run(memory(&files), &files, "conf/main.alt"),
+            r#"{"name":"app","db":{"name":"app","port":5432}}"#
+        );
+    }
+
+    #[test]
+    fn search_path() {
+        let files = files(&[
+            ("main.alt", "#include \"shared.alt\""),
+            ("lib/shared.alt", "shared = true"),
+        ]);
+        assert_eq!(
+            run(memory(&files).search_path("lib"), &files, "main.alt"),
+            r#"{"shared":true}"#
+        );
+        assert_eq!(
+            run(memory(&files), &files, "main.alt"),
+            "error: function eval error: shared.alt not found, searched shared.alt
+ --> main.alt:1:1
+  |
+1 | #include \"shared.alt\"
+  | ^^^^^^^^ in #include
+"
+        );
+    }
+
+    #[test]
+    fn cycles() {
+        let files = files(&[
+            ("a.alt", "#include \"b.alt\""),
+            ("b.alt", "x = 1\nc = @import \"./a.alt\""),
+        ]);
+        assert_eq!(
+            run(memory(&files), &files, "a.alt"),
+            "error: function eval error: include cycle: a.alt -> b.alt -> a.alt
+ --> b.alt:2:5
+  |
+2 | c = @import \"./a.alt\"
+  |     ^^^^^^^ in @import
+  |
+  = note: b.alt loaded by #include at a.alt:1:1
+"
+        );
+    }
+
+    #[test]
+    fn errors_name_the_chain() {
+        let files = files(&[
+            ("main.alt", "a = 1\nb = @import \"b.alt\""),
+            ("b.alt", "#include \"c.alt\""),
+            ("c.alt", "c = @std_url 1"),
+            ("broken.alt", "x = 1\nbroken = [1,,]"),
+            ("bad.alt", "#include \"broken.alt\""),
+        ]);
+        assert_eq!(
+            run(memory(&files), &files, "main.alt"),
+            "error: argument 1: expected string, found number
+ --> c.alt:1:14
+  |
+1 | c = @std_url 1
+  |              ^ passed to @std_url
+  |     -------- called here
+  |
+  = note: c.alt loaded by #include at b.alt:1:1
+  = note: b.alt loaded by @import at main.alt:2:5
+"
+        );
+        assert_eq!(
+            run(memory(&files), &files, "bad.alt"),
+            "error: unexpected `,` in array
+ --> broken.alt:2:13
+  |
+2 | broken = [1,,]
+  |             ^ expected value or `]`
+  |          - array opened here
+  |
+  = note: broken.alt loaded by #include at bad.alt:1:1
+"
+        );
+    }
+
+    #[test]
+    fn data_files() {
+        let files = files(&[
+            (
+                "conf/main.alt",
+                "cert = @read_text \"certs/a.pem\"
+                 hosts = @read_lines \"hosts\"
+                 extra = @read_json \"extra.json\"",
+            ),
+            ("conf/certs/a.pem", "-----BEGIN-----\n"),
+            ("conf/hosts", "a\r\nb\n"),
+            (
+                "conf/extra.json",
+                r#"{"b": [1, 2.5e0, null], "a": {"c": true, "c": false}}"#,
+            ),
+            ("conf/bad.alt", "x = @read_json \"bad.json\""),
+            ("conf/bad.json", "{\"a\": }"),
+            ("escape.alt", "x = @read_text \"../secret\""),
+            ("hosts.alt", "x = @import \"conf/hosts.alt\""),
+            ("conf/hosts.alt", "hosts = @read_lines \"hosts\""),
+        ]);
+        assert_eq!(
+            run(memory(&files), &files, "conf/main.alt"),
+            r#"{"cert":"-----BEGIN-----\n","hosts":["a","b"],"extra":{"b":[1,2.5,null],"a":{"c":false}}}"#
+        );
+        assert!(run(memory(&files), &files, "conf/bad.alt").starts_with(
+            "error: function eval error: invalid JSON in conf/bad.json: expected value at line 1 column 7"
+        ));
+
+        let sandboxed = Evaluator::default().files(Sandbox {
+            root: PathBuf::from("."),
+            files: files.clone(),
+        });
+        assert!(run(sandboxed, &files, "escape.alt")
+            .starts_with("error: function eval error: cannot read ../secret: outside of ."));
+        assert!(run(Evaluator::default().files(NoFiles), &files, "conf/main.alt").starts_with(
+            "error: function eval error: cannot read conf/certs/a.pem: reading files is disabled"
+        ));
+
+        // documents and data files can come from different file systems
+        assert!(
+            run(memory(&files).data_files(NoFiles), &files, "hosts.alt").starts_with(
+                "error: function eval error: cannot read conf/hosts: reading files is disabled"
+            )
+        );
+        assert_eq!(
+            run(
+                Evaluator::default()
+                    .files(NoFiles)
+                    .data_files(files.clone()),
+                &files,
+                "conf/hosts.alt"
+            ),
+            r#"{"hosts":["a","b"]}"#
+        );
+    }
+
+    #[test]
+    fn includes_of_other_values_are_errors() {
+        struct Stub;
+
+        impl eval::Evaluator for Stub {
+            fn value_function_eval(&mut self, _: &Call) -> Result<Value, EvalError> {
+                unreachable!("the stub does not call functions")
+            }
+            fn record_function_eval(&mut self, _: &Call) -> Result<Vec<Record>, EvalError> {
+                unreachable!("the stub does not call functions")
+            }
+            fn eval(&mut self, _: &Value) -> Result<Value, EvalError> {
+                Ok(Value::Number(1))
+            }
+        }
+
+        let files = RefCell::new(Files {
+            system: Rc::new(files(&[("a.alt", "a = 1")])),
+            ..Files::default()
+        });
+        let call = Call {
+            function: "include".to_string(),
+            args: vec![Value::String("a.alt".to_string()).into()],
+            ..Default::default()
+        };
+        let error = include(&files, &mut Stub, &call).unwrap_err();
+        assert_eq!(error.root().to_string(), "expected object, found number");
+    }
+
+    #[test]
+    fn includes_merge_with_the_policy() {
+        let files = files(&[
+            ("main.alt", "a = {x = 1}\n#include \"a.alt\""),
+            ("a.alt", "a = {y = 2}\nb = 1\nb = 2"),
+        ]);
+        let deep = memory(&files).with_merge_policy(crate::merge::MergePolicy::DeepMerge);
+        assert_eq!(
+            run(deep, &files, "main.alt"),
+            r#"{"a":{"x":1,"y":2},"b":2}"#
+        );
+    }
+
+    #[test]
+    fn references_see_included_records_merged() {
+        let files = files(&[
+            (
+                "main.alt",
+                "#include \"server.alt\"
+                 server = {port = 80}
+                 all = @ref server
+                 host = @ref server.host",
+            ),
+            ("server.alt", "server = {host = \"h\"; port = 1}"),
+        ]);
+        let with = |policy| memory(&files).with_merge_policy(policy);
+        assert_eq!(
+            run(
+                with(crate::merge::MergePolicy::DeepMerge),
+                &files,
+                "main.alt"
+            ),
+            r#"{"server":{"host":"h","port":80},"all":{"host":"h","port":80},"host":"h"}"#
+        );
+        assert!(run(
+            with(crate::merge::MergePolicy::LastWins),
+            &files,
+            "main.alt"
+        )
+        .starts_with("error: unresolved reference to server.host"));
+    }
+
+    #[test]
+    fn file_functions_are_registered() {
+        let files = files(&[
+            ("main.alt", "a = @read_txt \"a.txt\""),
+            ("fs.alt", "a = @fs.read_text \"a.txt\""),
+            ("text.alt", "a = @read_text \"a.txt\""),
+            ("json.alt", "a = @fs.read_json \"a.txt\""),
+            ("a.txt", "a"),
+        ]);
+        assert_eq!(
+            run(memory(&files), &files, "main.alt"),
+            "error: invalid function @read_txt
+ --> main.alt:1:5
+  |
+1 | a = @read_txt \"a.txt\"
+  |     ^^^^^^^^^ not defined
+  |
+  = help: did you mean @read_text?
+"
+        );
+
+        let namespaced = || {
+            Evaluator::with_files(|files| {
+                crate::goodies::registry().namespace("fs", files.without("read_json"))
+            })
+            .files(files.clone())
+        };
+        assert_eq!(run(namespaced(), &files, "fs.alt"), r#"{"a":"a"}"#);
+        assert!(
+            run(namespaced(), &files, "text.alt").starts_with("error: invalid function @read_text")
+        );
+        assert!(run(namespaced(), &files, "json.alt")
+            .starts_with("error: invalid function @fs.read_json"));
+    }
+
+    #[test]
+    fn included_records_conflict_at_the_include() {
+        let files = files(&[
+            ("main.alt", "a = 1\n#include \"a.alt\""),
+            ("a.alt", "a = 2"),
+        ]);
+        assert_eq!(
+            run(memory(&files), &files, "main.alt"),
+            "error: duplicate key a
+ --> main.alt:2:1
+  |
+1 | a = 1
+  | - first defined here
+2 | #include \"a.alt\"
+  | ^^^^^^^^ defined again here
+"
+        );
+    }
+}
@@ -0,0 +1,377 @@
+use super::{argument, strings::text, variadic};
+use crate::{
+    ast::{Call, Value},
+    convert::{FromValue, IntoValue},
+    eval::Error as EvalError,
+    registry::Registry,
+};
+use core::fmt;
+use std::error::Error as StdError;
+
+#[derive(Debug)]
+enum Error {
+    /// An integer operation, written out, whose result does not fit.
+    Overflow(String),
+    DivisionByZero,
+    /// A float operation that gave infinity or NaN, which have no literal.
+    NotFinite,
+    InvalidNumber(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow(operation) => write!(f, "{operation} overflows a 64-bit number"),
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::NotFinite => write!(f, "result is not a finite float"),
+            Self::InvalidNumber(s) => write!(f, "invalid number {s:?}"),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<Error> for EvalError {
+    fn from(value: Error) -> Self {
+        EvalError::Eval(Box::new(value))
+    }
+}
+
+/// A number or a float. Operations on two numbers give a number, any float
+/// makes them give a float.
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn float(self) -> f64 {
+        match self {
+            Self::Int(n) => n as f64,
+            Self::Float(f) => f,
+        }
+    }
+}
+
+impl FromValue for Num {
+    fn from_value(value: &Value) -> Result<Self, EvalError> {
+        match value {
+            Value::Number(n) => Ok(Self::Int(*n)),
+            Value::Float(f) => Ok(Self::Float(*f)),
+            Value::Typed(t) => Self::from_value(&t.value),
+            _ => Err(EvalError::Type {
+                expected: "number or float",
+                found: value.type_name(),
+            }),
+        }
+    }
+}
+
+impl IntoValue for Num {
+    fn into_value(self) -> Value {
+        match self {
+            Self::Int(n) => Value::Number(n),
+            Self::Float(f) => Value::Float(f),
+        }
+    }
+}
+
+fn finite(f: f64) -> Result<f64, EvalError> {
+    if f.is_finite() {
+        Ok(f)
+    } else {
+        Err(Error::NotFinite.into())
+    }
+}
+
+/// `f` truncated towards zero, if it fits a number.
+fn truncate(f: f64, operation: &str) -> Result<i64, EvalError> {
+    // `i64::MAX as f64` rounds up to 2^63, which no longer fits.
+    if f.is_finite() && f >= i64::MIN as f64 && f < i64::MAX as f64 {
+        Ok(f as i64)
+    } else {
+        Err(Error::Overflow(format!("{operation}({f:?})")).into())
+    }
+}
+
+/// The operands of `@add(1, 2)`, or of `@add [1 2]` when the only argument
+/// is an array, in which case its elements count as the arguments.
+fn operands(call: &Call) -> Result<Vec<Num>, EvalError> {
+    let values = match variadic(call)?.as_slice() {
+        [Value::Array(values)] => values.iter().map(|e| &e.value).collect(),
+        values => values.to_vec(),
+    };
+    if values.is_empty() {
+        return Err(EvalError::Arity {
+            min: 1,
+            max: None,
+            found: 0,
+        });
+    }
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| Num::from_value(value).map_err(argument(i + 1)))
+        .collect()
+}
+
+/// An operation on two operands, folded over all of them from the left.
+struct Operation {
+    symbol: &'static str,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+    /// Whether a zero right operand is an error.
+    divides: bool,
+}
+
+impl Operation {
+    fn apply(&self, a: Num, b: Num) -> Result<Num, EvalError> {
+        if self.divides && b.float() == 0.0 {
+            return Err(Error::DivisionByZero.into());
+        }
+        match (a, b) {
+            (Num::Int(a), Num::Int(b)) => (self.int)(a, b)
+                .map(Num::Int)
+                .ok_or_else(|| Error::Overflow(format!("{a} {} {b}", self.symbol)).into()),
+            _ => finite((self.float)(a.float(), b.float())).map(Num::Float),
+        }
+    }
+
+    fn fold(&self, call: &Call) -> Result<Value, EvalError> {
+        let mut operands = operands(call)?.into_iter();
+        let first = operands.next().unwrap_or(Num::Int(0));
+        operands
+            .try_fold(first, |a, b| self.apply(a, b))
+            .map(IntoValue::into_value)
+    }
+}
+
+const ADD: Operation = Operation {
+    symbol: "+",
+    int: i64::checked_add,
+    float: |a, b| a + b,
+    divides: false,
+};
+const SUB: Operation = Operation {
+    symbol: "-",
+    int: i64::checked_sub,
+    float: |a, b| a - b,
+    divides: false,
+};
+const MUL: Operation = Operation {
+    symbol: "*",
+    int: i64::checked_mul,
+    float: |a, b| a * b,
+    divides: false,
+};
+/// Division of two numbers truncates, like in Rust.
+const DIV: Operation = Operation {
+    symbol: "/",
+    int: i64::checked_div,
+    float: |a, b| a / b,
+    divides: true,
+};
+/// The remainder has the sign of the dividend, like in Rust.
+const MOD: Operation = Operation {
+    symbol: "%",
+    int: i64::checked_rem,
+    float: |a, b| a % b,
+    divides: true,
+};
+
+/// The smallest operand if `ordering` is `Less`, the largest if `Greater`.
+fn extreme(call: &Call, ordering: std::cmp::Ordering) -> Result<Value, EvalError> {
+    let mut operands = operands(call)?.into_iter();
+    let first = operands.next().unwrap_or(Num::Int(0));
+    Ok(operands
+        .fold(first, |a, b| {
+            if b.float().total_cmp(&a.float()) == ordering {
+                b
+            } else {
+                a
+            }
+        })
+        .into_value())
+}
+
+fn abs(n: Num) -> Result<Num, EvalError> {
+    match n {
+        Num::Int(n) => n
+            .checked_abs()
+            .map(Num::Int)
+            .ok_or_else(|| Error::Overflow(format!("abs({n})")).into()),
+        Num::Float(f) => Ok(Num::Float(f.abs())),
+    }
+}
+
+/// `@round 2.5` is the number 3, halves rounding away from zero.
+/// `@round(3.14159, 2)` keeps two decimal digits and is the float 3.14.
+fn round(n: Num, digits: Option<i32>) -> Result<Num, EvalError> {
+    match (n, digits) {
+        (Num::Int(_), _) => Ok(n),
+        (Num::Float(f), None) => truncate(f.round(), "round").map(Num::Int),
+        (Num::Float(f), Some(digits)) => {
+            let scale = 10f64.powi(digits);
+            finite((f * scale).round() / scale).map(Num::Float)
+        }
+    }
+}
+
+/// `@int` truncates floats and parses strings.
+fn int(value: Value) -> Result<i64, EvalError> {
+    match value {
+        Value::Number(n) => Ok(n),
+        Value::Float(f) => truncate(f, "int"),
+        Value::String(s) => s.trim().parse().map_err(|_| Error::InvalidNumber(s).into()),
+        Value::Typed(t) => int(*t.value),
+        _ => Err(argument(1)(EvalError::Type {
+            expected: "number, float or string",
+            found: value.type_name(),
+        })),
+    }
+}
+
+/// `@float` converts numbers and parses strings.
+fn float(value: Value) -> Result<f64, EvalError> {
+    match value {
+        Value::Number(n) => Ok(n as f64),
+        Value::Float(f) => Ok(f),
+        Value::String(s) => match s.trim().parse() {
+            Ok(f) => finite(f),
+            Err(_) => Err(Error::InvalidNumber(s).into()),
+        },
+        Value::Typed(t) => float(*t.value),
+        _ => Err(argument(1)(EvalError::Type {
+            expected: "number, float or string",
+            found: value.type_name(),
+        })),
+    }
+}
+
+/// `@add`, `@sub`, `@mul`, `@div`, `@mod`, `@min`, `@max`, `@abs`, `@round`
+/// and the conversions `@int`, `@float` and `@string`.
+pub(super) fn registry() -> Registry {
+    Registry::new()
+        .value_raw("add", |call| ADD.fold(call))
+        .value_raw("sub", |call| SUB.fold(call))
+        .value_raw("mul", |call| MUL.fold(call))
+        .value_raw("div", |call| DIV.fold(call))
+        .value_raw("mod", |call| MOD.fold(call))
+        .value_raw("min", |call| extreme(call, std::cmp::Ordering::Less))
+        .value_raw("max", |call| extreme(call, std::cmp::Ordering::Greater))
+        .value("abs", abs)
+        .value("round", round)
+        .value("int", int)
+        .value("float", float)
+        .value("string", |value: Value| text(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize, parser::parse};
+
+    /// Evaluates `x = {source}` and returns `x`.
+    fn eval(source: &str) -> Result<Value, EvalError> {
+        let tokens = tokenize(&format!("x = {source}")).expect("valid tokens");
+        match registry().eval(&parse(&tokens).expect("valid document"))? {
+            Value::Object(mut records) => Ok(records.remove(0).value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn error(source: &str) -> String {
+        eval(source).unwrap_err().root().to_string()
+    }
+
+    #[test]
+    fn arithmetic() -> Result<(), EvalError> {
+        assert_eq!(eval("@mul [60 1000]")?, Value::Number(60000));
+        assert_eq!(eval("@add(1, 2, 3)")?, Value::Number(6));
+        assert_eq!(eval("@add(1, 0.5)")?, Value::Float(1.5));
+        assert_eq!(eval("@sub [10 3 2]")?, Value::Number(5));
+        assert_eq!(eval("@div(7, 2)")?, Value::Number(3));
+        assert_eq!(eval("@div(7.0, 2)")?, Value::Float(3.5));
+        assert_eq!(eval("@mod(-7, 3)")?, Value::Number(-1));
+        assert_eq!(eval("@add 5")?, Value::Number(5));
+        assert_eq!(eval("@mul(@add(1, 1), 3)")?, Value::Number(6));
+        Ok(())
+    }
+
+    #[test]
+    fn rounding() -> Result<(), EvalError> {
+        assert_eq!(eval("@min [3 1.5 2]")?, Value::Float(1.5));
+        assert_eq!(eval("@max(3, 1.5, 2)")?, Value::Number(3));
+        assert_eq!(eval("@abs -4")?, Value::Number(4));
+        assert_eq!(eval("@abs -0.5")?, Value::Float(0.5));
+        assert_eq!(eval("@round 2.5")?, Value::Number(3));
+        assert_eq!(eval("@round -2.5")?, Value::Number(-3));
+        assert_eq!(eval("@round 7")?, Value::Number(7));
+        assert_eq!(eval("@round(1.23456, 2)")?, Value::Float(1.23));
+        Ok(())
+    }
+
+    #[test]
+    fn conversions() -> Result<(), EvalError> {
+        assert_eq!(eval("@int 2.9")?, Value::Number(2));
+        assert_eq!(eval("@int -2.9")?, Value::Number(-2));
+        assert_eq!(eval("@int \" 42 \"")?, Value::Number(42));
+        assert_eq!(eval("@float 2")?, Value::Float(2.0));
+        assert_eq!(eval("@float \"1e3\"")?, Value::Float(1000.0));
+        assert_eq!(eval("@string 1.5")?, Value::String("1.5".to_string()));
+        assert_eq!(
+            eval("@string @mul [60 1000]")?,
+            Value::String("60000".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("@add(9223372036854775807, 1)"),
+            "function eval error: 9223372036854775807 + 1 overflows a 64-bit number"
+        );
+        assert_eq!(
+            error("@mul [-9223372036854775808 -1]"),
+            "function eval error: -9223372036854775808 * -1 overflows a 64-bit number"
+        );
+        assert_eq!(
+            error("@abs -9223372036854775808"),
+            "function eval error: abs(-9223372036854775808) overflows a 64-bit number"
+        );
+        assert_eq!(
+            error("@int 1e300"),
+            "function eval error: int(1e300) overflows a 64-bit number"
+        );
+        assert_eq!(error("@div(1, 0)"), "function eval error: division by zero");
+        assert_eq!(
+            error("@mod(1.5, 0.0)"),
+            "function eval error: division by zero"
+        );
+        assert_eq!(
+            error("@mul(1e300, 1e300)"),
+            "function eval error: result is not a finite float"
+        );
+        assert_eq!(
+            error("@int \"12x\""),
+            "function eval error: invalid number \"12x\""
+        );
+        assert_eq!(
+            error("@float \"inf\""),
+            "function eval error: result is not a finite float"
+        );
+        assert_eq!(
+            error("@add(1, \"2\")"),
+            "argument 2: expected number or float, found string"
+        );
+        assert_eq!(
+            error("@int [1]"),
+            "argument 1: expected number, float or string, found array"
+        );
+        assert_eq!(error("@max []"), "expected at least 1 argument, found 0");
+        assert_eq!(error("@round()"), "expected 1 to 2 arguments, found 0");
+        assert_eq!(error("@add(1, b = 2)"), "unexpected argument b");
+    }
+}
@@ -0,0 +1,287 @@
+use super::{argument, variadic};
+use crate::{
+    ast::{Call, Value},
+    convert::FromValue,
+    eval::Error as EvalError,
+    registry::Registry,
+};
+use core::fmt;
+use regex::Regex;
+use std::error::Error as StdError;
+
+#[derive(Debug)]
+enum Error {
+    /// A `{` in a format string that is never closed, at the given
+    /// character.
+    UnclosedPlaceholder(usize),
+    /// A `}` in a format string that closes nothing, at the given character.
+    UnmatchedBrace(usize),
+    /// A placeholder in a format string with no argument to fill it.
+    MissingArgument(String),
+    InvalidRegex(regex::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnclosedPlaceholder(i) => {
+                write!(f, "unclosed `{{` at character {i} of format string")
+            }
+            Self::UnmatchedBrace(i) => write!(
+                f,
+                "unmatched `}}` at character {i} of format string, write `}}}}` for a literal one"
+            ),
+            Self::MissingArgument(name) => {
+                write!(f, "no argument for `{{{name}}}` in format string")
+            }
+            Self::InvalidRegex(e) => write!(f, "invalid regex: {e}"),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl From<Error> for EvalError {
+    fn from(value: Error) -> Self {
+        EvalError::Eval(Box::new(value))
+    }
+}
+
+/// A scalar as it is written into a string.
+pub(super) fn text(value: &Value) -> Result<String, EvalError> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Typed(t) => text(&t.value),
+        _ => Err(EvalError::Type {
+            expected: "string, number or boolean",
+            found: value.type_name(),
+        }),
+    }
+}
+
+/// Splits on `separator`, or on whitespace without one. An empty separator
+/// splits into characters.
+fn split(s: String, separator: Option<String>) -> Result<Vec<String>, EvalError> {
+    Ok(match separator.as_deref() {
+        None => s.split_whitespace().map(str::to_string).collect(),
+        Some("") => s.chars().map(String::from).collect(),
+        Some(separator) => s.split(separator).map(str::to_string).collect(),
+    })
+}
+
+fn join(separator: String, values: Vec<Value>) -> Result<String, EvalError> {
+    let texts = values
+        .iter()
+        .map(text)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(argument(2))?;
+    Ok(texts.join(&separator))
+}
+
+/// `@concat("a", 1, true)` is `"a1true"`.
+pub(super) fn concat(call: &Call) -> Result<Value, EvalError> {
+    let mut out = String::new();
+    for (i, value) in variadic(call)?.into_iter().enumerate() {
+        out.push_str(&text(value).map_err(argument(i + 1))?);
+    }
+    Ok(Value::String(out))
+}
+
+/// `@format("{} is {age}, {0} again", name, age = 3)`. Empty placeholders take
+/// the positional arguments after the format string in turn, numbered ones
+/// pick one of them and named ones a named argument. `{{` and `}}` are
+/// literal braces.
+fn format(call: &Call) -> Result<Value, EvalError> {
+    let mut positional = call.positional();
+    let template = match positional.next() {
+        Some(template) => String::from_value(template).map_err(argument(1))?,
+        None => {
+            return Err(EvalError::Arity {
+                min: 1,
+                max: None,
+                found: 0,
+            })
+        }
+    };
+    let args = positional.collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let mut next = 0;
+    let mut chars = template.chars().enumerate().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '{' if chars.next_if(|(_, ch)| *ch == '{').is_some() => out.push('{'),
+            '}' if chars.next_if(|(_, ch)| *ch == '}').is_some() => out.push('}'),
+            '}' => return Err(Error::UnmatchedBrace(i).into()),
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '}')) => break,
+                        Some((_, ch)) => name.push(ch),
+                        None => return Err(Error::UnclosedPlaceholder(i).into()),
+                    }
+                }
+                let value = if name.is_empty() {
+                    next += 1;
+                    args.get(next - 1).copied()
+                } else if let Ok(index) = name.parse::<usize>() {
+                    args.get(index).copied()
+                } else {
+                    call.named(&name)
+                };
+                let value = value.ok_or(Error::MissingArgument(name))?;
+                out.push_str(&text(value)?);
+            }
+            _ => out.push(ch),
+        }
+    }
+    Ok(Value::String(out))
+}
+
+fn regex(pattern: &str) -> Result<Regex, EvalError> {
+    Regex::new(pattern).map_err(|e| Error::InvalidRegex(e).into())
+}
+
+/// `@regex.replace(s, pattern, replacement)` replaces every match, with `$1`
+/// or `${name}` in `replacement` standing for a capture group.
+fn regex_replace(s: String, pattern: String, replacement: String) -> Result<String, EvalError> {
+    Ok(regex(&pattern)?
+        .replace_all(&s, replacement.as_str())
+        .into_owned())
+}
+
+/// `@upper`, `@lower`, `@trim`, `@split`, `@join`, `@replace`, `@format`,
+/// `@starts_with`, `@regex.match` and `@regex.replace`. `@concat` and `@len`
+/// are with the collection functions, which also take strings.
+pub(super) fn registry() -> Registry {
+    let regexes = Registry::new()
+        .value("match", |s: String, pattern: String| {
+            Ok(regex(&pattern)?.is_match(&s))
+        })
+        .value("replace", regex_replace);
+
+    Registry::new()
+        .value("upper", |s: String| Ok(s.to_uppercase()))
+        .value("lower", |s: String| Ok(s.to_lowercase()))
+        .value("trim", |s: String| Ok(s.trim().to_string()))
+        .value("split", split)
+        .value("join", join)
+        .value("replace", |s: String, from: String, to: String| {
+            Ok(s.replace(&from, &to))
+        })
+        .value_raw("format", format)
+        .value("starts_with", |s: String, prefix: String| {
+            Ok(s.starts_with(&prefix))
+        })
+        .namespace("regex", regexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::Evaluator, lexer::tokenize, parser::parse};
+
+    /// Evaluates `x = {source}` and returns `x`.
+    fn eval(source: &str) -> Result<Value, EvalError> {
+        let tokens = tokenize(&format!("x = {source}")).expect("valid tokens");
+        match registry().eval(&parse(&tokens).expect("valid document"))? {
+            Value::Object(mut records) => Ok(records.remove(0).value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn strings(s: &[&str]) -> Value {
+        Value::array(s.iter().map(|s| string(s)))
+    }
+
+    fn error(source: &str) -> String {
+        eval(source).unwrap_err().root().to_string()
+    }
+
+    #[test]
+    fn simple() -> Result<(), EvalError> {
+        assert_eq!(eval("@upper \"aBc\"")?, string("ABC"));
+        assert_eq!(eval("@lower \"aBc\"")?, string("abc"));
+        assert_eq!(eval("@trim \"  a b \\n\"")?, string("a b"));
+        assert_eq!(eval("@replace(\"a-b-c\", \"-\", \"+\")")?, string("a+b+c"));
+        assert_eq!(
+            eval("@starts_with(\"https://x\", \"https\")")?,
+            Value::Bool(true)
+        );
+        assert_eq!(eval("@upper @trim \" a \"")?, string("A"));
+        Ok(())
+    }
+
+    #[test]
+    fn split_and_join() -> Result<(), EvalError> {
+        assert_eq!(eval("@split \" a  b\\tc \"")?, strings(&["a", "b", "c"]));
+        assert_eq!(
+            eval("@split(\"a,b,,c\", \",\")")?,
+            strings(&["a", "b", "", "c"])
+        );
+        assert_eq!(eval("@split(\"abc\", \"\")")?, strings(&["a", "b", "c"]));
+        assert_eq!(eval("@join(\", \", [\"a\" 1 true])")?, string("a, 1, true"));
+        assert_eq!(eval("@join(\"\", [])")?, string(""));
+        assert_eq!(
+            error("@join(\", \", [\"a\" [1]])"),
+            "argument 2: expected string, number or boolean, found array"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn format() -> Result<(), EvalError> {
+        assert_eq!(
+            eval("@format(\"{} is {}, {0} again\", \"x\", 1)")?,
+            string("x is 1, x again")
+        );
+        assert_eq!(
+            eval("@format(\"{host}:{port}\", host = \"localhost\", port = 80)")?,
+            string("localhost:80")
+        );
+        assert_eq!(eval("@format \"{{literal}}\"")?, string("{literal}"));
+        assert_eq!(
+            error("@format(\"{} {}\", 1)"),
+            "function eval error: no argument for `{}` in format string"
+        );
+        assert_eq!(
+            error("@format(\"{name}\")"),
+            "function eval error: no argument for `{name}` in format string"
+        );
+        assert_eq!(
+            error("@format \"ab {\""),
+            "function eval error: unclosed `{` at character 3 of format string"
+        );
+        assert_eq!(
+            error("@format \"a}b\""),
+            "function eval error: unmatched `}` at character 1 of format string, write `}}` for a literal one"
+        );
+        assert_eq!(error("@format()"), "expected at least 1 argument, found 0");
+        Ok(())
+    }
+
+    #[test]
+    fn regexes() -> Result<(), EvalError> {
+        assert_eq!(
+            eval("@regex.match(\"v1.2.3\", r\"^v\\d+(\\.\\d+)*$\")")?,
+            Value::Bool(true)
+        );
+        assert_eq!(eval("@regex.match(\"abc\", \"^b\")")?, Value::Bool(false));
+        assert_eq!(
+            eval("@regex.replace(\"2024-01-31\", r\"(\\d+)-(\\d+)-(\\d+)\", \"$3.$2.$1\")")?,
+            string("31.01.2024")
+        );
+        assert!(
+            error("@regex.match(\"a\", \"(\")").starts_with("function eval error: invalid regex")
+        );
+        Ok(())
+    }
+}
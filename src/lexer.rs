@@ -1,12 +1,16 @@
 use core::fmt;
+use std::error::Error as StdError;
 use std::{iter::Peekable, str::CharIndices};
 
-#[derive(Debug, PartialEq, Eq, Default, Clone)]
+#[derive(Debug, PartialEq, Default, Clone)]
 pub enum TokenKind {
     // Value carrying
     ID(String),
-    Number(i32),
+    Number(i64),
+    Float(f64),
     String(String),
+    Bool(bool),
+    Null,
 
     // Symbols
     Separator,
@@ -15,6 +19,9 @@ pub enum TokenKind {
     RightBrace,
     LeftBracket,
     RightBracket,
+    LeftParen,
+    RightParen,
+    Comma,
 
     Dot,
 
@@ -26,6 +33,35 @@ pub enum TokenKind {
     EndOfInput,
 }
 
+/// Describes a token for error messages. Literals are named by their kind
+/// only, identifiers with an empty name stand for any identifier.
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ID(id) if id.is_empty() => write!(f, "identifier"),
+            Self::ID(id) => write!(f, "identifier `{id}`"),
+            Self::Number(_) => write!(f, "number"),
+            Self::Float(_) => write!(f, "float"),
+            Self::String(_) => write!(f, "string"),
+            Self::Bool(_) => write!(f, "boolean"),
+            Self::Null => write!(f, "`null`"),
+            Self::Separator => write!(f, "separator"),
+            Self::Assign => write!(f, "`=`"),
+            Self::LeftBrace => write!(f, "`{{`"),
+            Self::RightBrace => write!(f, "`}}`"),
+            Self::LeftBracket => write!(f, "`[`"),
+            Self::RightBracket => write!(f, "`]`"),
+            Self::LeftParen => write!(f, "`(`"),
+            Self::RightParen => write!(f, "`)`"),
+            Self::Comma => write!(f, "`,`"),
+            Self::Dot => write!(f, "`.`"),
+            Self::ValueCall => write!(f, "`@`"),
+            Self::RecordCall => write!(f, "`#`"),
+            Self::EndOfInput => write!(f, "end of input"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Default, Clone, Copy)]
 pub struct FilePos {
     pub start: usize,
@@ -38,14 +74,100 @@ impl fmt::Display for FilePos {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorTypes {
+    InvalidCharacter(char),
+    InvalidNumber,
+    IntegerOverflow,
+    FloatOverflow,
+    UnterminatedString,
+    UnterminatedComment,
+    InvalidEscape,
+    InvalidUnicodeEscape,
+}
+
+impl fmt::Display for ErrorTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::InvalidCharacter(ch) => write!(f, "unexpected character {ch:?}"),
+            Self::InvalidNumber => write!(f, "invalid number literal"),
+            Self::IntegerOverflow => write!(f, "integer literal does not fit in 64 bits"),
+            Self::FloatOverflow => write!(f, "float literal is too large"),
+            Self::UnterminatedString => write!(f, "unterminated string"),
+            Self::UnterminatedComment => write!(f, "unterminated block comment"),
+            Self::InvalidEscape => write!(f, "invalid escape sequence"),
+            Self::InvalidUnicodeEscape => write!(f, "invalid unicode escape"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    pub error: ErrorTypes,
+    pub pos: FilePos,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.error, self.pos)
+    }
+}
+
+impl StdError for Error {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CommentKind {
+    /// `// ...` up to the end of the line
+    Line,
+    /// `/* ... */`
+    Block,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Comment {
+    pub kind: CommentKind,
+    /// The text between the delimiters.
+    pub text: String,
+    pub pos: FilePos,
+    /// Whether the comment starts its line, as opposed to trailing after
+    /// other tokens on the same line.
+    pub own_line: bool,
+}
+
+impl fmt::Display for Comment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            CommentKind::Line => write!(f, "//{}", self.text),
+            CommentKind::Block => write!(f, "/*{}*/", self.text),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
 pub struct Token {
     pub kind: TokenKind,
     pub pos: FilePos,
+    /// Comments between the previous token and this one. The parser skips
+    /// them, tooling can pick them up.
+    pub comments: Vec<Comment>,
 }
 
 impl Token {}
 
+/// Checks whether `s` would be lexed as a single [`TokenKind::ID`].
+pub fn is_identifier(s: &str) -> bool {
+    if matches!(s, "true" | "false" | "null") {
+        return false;
+    }
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(ch) if ch.is_alphanumeric() && !ch.is_ascii_digit() => {
+            chars.all(|ch| ch.is_alphanumeric() || ch == '-' || ch == '_')
+        }
+        _ => false,
+    }
+}
+
 fn skip_spaces(it: &mut Peekable<CharIndices>) {
     while let Some((_, ch)) = it.peek() {
         if ch.is_whitespace() && *ch != '\n' {
@@ -56,37 +178,144 @@ fn skip_spaces(it: &mut Peekable<CharIndices>) {
     }
 }
 
-fn lex_number(it: &mut Peekable<CharIndices>) -> Token {
+/// Takes digits of the given radix, allowing `_` between them.
+fn lex_digits(
+    it: &mut Peekable<CharIndices>,
+    len: usize,
+    radix: u32,
+    x: &mut String,
+) -> Result<(), Error> {
+    let mut first = true;
+    while let Some((pos, ch)) = it.peek().copied() {
+        if ch.is_digit(radix) {
+            x.push(ch);
+        } else if ch == '_' && !first {
+            let mut ahead = it.clone();
+            ahead.next();
+            if !matches!(ahead.peek(), Some((_, ch)) if ch.is_digit(radix)) {
+                return Err(Error {
+                    error: ErrorTypes::InvalidNumber,
+                    pos: FilePos {
+                        start: pos,
+                        end: pos + 1,
+                    },
+                });
+            }
+        } else {
+            break;
+        }
+        first = false;
+        it.next();
+    }
+
+    if first {
+        let pos = it.peek().map_or(len, |(pos, _)| *pos);
+        return Err(Error {
+            error: ErrorTypes::InvalidNumber,
+            pos: FilePos {
+                start: pos,
+                end: pos,
+            },
+        });
+    }
+    Ok(())
+}
+
+/// Lexes `-12`, `1_000`, `0xFF`, `0o17`, `0b1010`, `1.5` and `1e-9`.
+/// Literals with a fraction or an exponent become floats.
+fn lex_number(it: &mut Peekable<CharIndices>, len: usize) -> Result<Token, Error> {
     let mut token: Token = Default::default();
+    let Some((start, _)) = it.peek().copied() else {
+        return Ok(token);
+    };
+    token.pos.start = start;
 
-    let mut x = 0;
-    let mut first = true;
-    loop {
-        match it.peek() {
-            None => {
-                token.kind = TokenKind::Number(x);
-                token.pos.end = token.pos.start + x.ilog10() as usize + 1;
-                break;
+    let mut x = String::new();
+    if let Some((_, '-')) = it.peek() {
+        x.push('-');
+        it.next();
+    }
+
+    let mut ahead = it.clone();
+    let radix = match (ahead.next(), ahead.next()) {
+        (Some((_, '0')), Some((_, 'x'))) => 16,
+        (Some((_, '0')), Some((_, 'o'))) => 8,
+        (Some((_, '0')), Some((_, 'b'))) => 2,
+        _ => 10,
+    };
+
+    let mut float = false;
+    let result = if radix == 10 {
+        lex_digits(it, len, 10, &mut x)?;
+        if let Some((_, '.')) = it.peek() {
+            float = true;
+            x.push('.');
+            it.next();
+            lex_digits(it, len, 10, &mut x)?;
+        }
+        if let Some((_, 'e' | 'E')) = it.peek() {
+            float = true;
+            x.push('e');
+            it.next();
+            if let Some((_, ch @ ('-' | '+'))) = it.peek().copied() {
+                x.push(ch);
+                it.next();
             }
-            Some((pos, ch)) => {
-                if first {
-                    token.pos.start = *pos;
-                    first = false;
-                }
-                if let Some(digit) = ch.to_digit(10) {
-                    x *= 10;
-                    x += digit as i32;
-                    it.next();
-                } else {
-                    token.kind = TokenKind::Number(x);
-                    token.pos.end = *pos;
+            lex_digits(it, len, 10, &mut x)?;
+        }
+        if float {
+            x.parse::<f64>()
+                .map(TokenKind::Float)
+                .map_err(|_| ErrorTypes::InvalidNumber)
+        } else {
+            x.parse::<i64>()
+                .map(TokenKind::Number)
+                .map_err(|_| ErrorTypes::IntegerOverflow)
+        }
+    } else {
+        it.nth(1);
+        lex_digits(it, len, radix, &mut x)?;
+        i64::from_str_radix(&x, radix)
+            .map(TokenKind::Number)
+            .map_err(|_| ErrorTypes::IntegerOverflow)
+    };
+    token.pos.end = it.peek().map_or(len, |(pos, _)| *pos);
+
+    if let Some((_, ch)) = it.peek() {
+        if ch.is_alphanumeric() || *ch == '_' || *ch == '.' {
+            let mut end = token.pos.end;
+            while let Some((pos, ch)) = it.peek().copied() {
+                if !(ch.is_alphanumeric() || ch == '_' || ch == '.') {
                     break;
                 }
+                end = pos + ch.len_utf8();
+                it.next();
             }
+            return Err(Error {
+                error: ErrorTypes::InvalidNumber,
+                pos: FilePos { start, end },
+            });
         }
     }
 
-    token
+    match result {
+        Ok(TokenKind::Float(f)) if f.is_infinite() => Err(ErrorTypes::FloatOverflow),
+        result => result,
+    }
+    .map(|kind| Token { kind, ..token })
+    .map_err(|error| Error {
+        error,
+        pos: token.pos,
+    })
+}
+
+fn keyword(x: String) -> TokenKind {
+    match x.as_str() {
+        "true" => TokenKind::Bool(true),
+        "false" => TokenKind::Bool(false),
+        "null" => TokenKind::Null,
+        _ => TokenKind::ID(x),
+    }
 }
 
 fn lex_ident(it: &mut Peekable<CharIndices>) -> Token {
@@ -98,7 +327,7 @@ fn lex_ident(it: &mut Peekable<CharIndices>) -> Token {
         match it.peek() {
             None => {
                 token.pos.end = token.pos.start + x.len();
-                token.kind = TokenKind::ID(x);
+                token.kind = keyword(x);
                 break;
             }
             Some((pos, ch)) => {
@@ -110,7 +339,7 @@ fn lex_ident(it: &mut Peekable<CharIndices>) -> Token {
                     x.push(*ch);
                     it.next();
                 } else {
-                    token.kind = TokenKind::ID(x);
+                    token.kind = keyword(x);
                     token.pos.end = *pos;
                     break;
                 }
@@ -121,34 +350,281 @@ fn lex_ident(it: &mut Peekable<CharIndices>) -> Token {
     token
 }
 
-fn lex_string(it: &mut Peekable<CharIndices>) -> Token {
+/// Takes `count` copies of `ch` if they are next in `it`.
+fn take_repeated(it: &mut Peekable<CharIndices>, ch: char, count: usize) -> bool {
+    let mut ahead = it.clone();
+    for _ in 0..count {
+        match ahead.next() {
+            Some((_, c)) if c == ch => (),
+            _ => return false,
+        }
+    }
+    *it = ahead;
+    true
+}
+
+fn lex_escape<I>(it: &mut I, start: usize) -> Result<char, Error>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    let invalid = |end| Error {
+        error: ErrorTypes::InvalidEscape,
+        pos: FilePos { start, end },
+    };
+
+    let Some((pos, ch)) = it.next() else {
+        return Err(invalid(start + 1));
+    };
+    let escaped = match ch {
+        '"' => '"',
+        '\\' => '\\',
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        '0' => '\0',
+        'u' => {
+            if !matches!(it.next(), Some((_, '{'))) {
+                return Err(invalid(pos + 1));
+            }
+            let mut digits = String::new();
+            let mut end = pos + 1;
+            loop {
+                match it.next() {
+                    Some((pos, '}')) => {
+                        end = pos + 1;
+                        break;
+                    }
+                    Some((pos, ch)) if ch.is_ascii_hexdigit() && digits.len() < 6 => {
+                        digits.push(ch);
+                        end = pos + 1;
+                    }
+                    Some((pos, ch)) => return Err(invalid(pos + ch.len_utf8())),
+                    None => return Err(invalid(end)),
+                }
+            }
+            return u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or(Error {
+                    error: ErrorTypes::InvalidUnicodeEscape,
+                    pos: FilePos { start, end },
+                });
+        }
+        _ => return Err(invalid(pos + ch.len_utf8())),
+    };
+    Ok(escaped)
+}
+
+fn unescape(chars: &[(usize, char)]) -> Result<String, Error> {
+    let mut x = String::new();
+    let mut it = chars.iter().copied();
+    while let Some((pos, ch)) = it.next() {
+        if ch == '\\' {
+            x.push(lex_escape(&mut it, pos)?);
+        } else {
+            x.push(ch);
+        }
+    }
+    Ok(x)
+}
+
+/// Removes the indentation shared by all lines of a multi-line string, the
+/// line break after the opening quotes and the whitespace before the closing
+/// ones.
+fn strip_indent(chars: &[(usize, char)]) -> Vec<(usize, char)> {
+    let is_blank = |line: &[(usize, char)]| line.iter().all(|(_, ch)| ch.is_whitespace());
+    let mut lines = chars
+        .split_inclusive(|(_, ch)| *ch == '\n')
+        .collect::<Vec<_>>();
+    if lines.len() > 1 && is_blank(lines[0]) {
+        lines.remove(0);
+    }
+    // closing quotes on their own line only count towards the indentation
+    let closing = match lines.last() {
+        Some(line) if matches!(line.last(), Some((_, '\n'))) => Some(0),
+        Some(line) if lines.len() > 1 && is_blank(line) => lines.pop().map(<[_]>::len),
+        _ => None,
+    };
+
+    let indent = lines
+        .iter()
+        .filter(|line| !is_blank(line))
+        .map(|line| {
+            line.iter()
+                .take_while(|(_, ch)| ch.is_whitespace() && *ch != '\n')
+                .count()
+        })
+        .chain(closing)
+        .min()
+        .unwrap_or(0);
+
+    lines
+        .iter()
+        .flat_map(|line| {
+            let width = line.iter().take_while(|(_, ch)| *ch != '\n').count();
+            line.iter().skip(indent.min(width))
+        })
+        .copied()
+        .collect()
+}
+
+fn lex_string(it: &mut Peekable<CharIndices>) -> Result<Token, Error> {
     let mut token: Token = Default::default();
+    let Some((start, _)) = it.next() else {
+        return Ok(token);
+    };
+    token.pos.start = start;
+
+    let triple = take_repeated(it, '"', 2);
+    let quotes = if triple { 3 } else { 1 };
+    let mut raw = Vec::new();
+    loop {
+        match it.next() {
+            Some((pos, '"')) if take_repeated(it, '"', quotes - 1) => {
+                token.pos.end = pos + quotes;
+                break;
+            }
+            Some((pos, '\\')) => {
+                raw.push((pos, '\\'));
+                raw.extend(it.next());
+            }
+            Some(x) => raw.push(x),
+            None => {
+                return Err(Error {
+                    error: ErrorTypes::UnterminatedString,
+                    pos: FilePos {
+                        start,
+                        end: raw
+                            .last()
+                            .map_or(start + quotes, |(pos, ch)| pos + ch.len_utf8()),
+                    },
+                })
+            }
+        }
+    }
+
+    if triple {
+        raw = strip_indent(&raw);
+    }
+    token.kind = TokenKind::String(unescape(&raw)?);
+    Ok(token)
+}
+
+/// Lexes `r"..."` or `r#"..."#` with any number of `#`s. Nothing inside a
+/// raw string is escaped.
+fn lex_raw_string(it: &mut Peekable<CharIndices>) -> Result<Option<Token>, Error> {
+    let mut ahead = it.clone();
+    let Some((start, 'r')) = ahead.next() else {
+        return Ok(None);
+    };
+    let mut hashes = 0;
+    loop {
+        match ahead.next() {
+            Some((_, '#')) => hashes += 1,
+            Some((_, '"')) => break,
+            _ => return Ok(None),
+        }
+    }
+    *it = ahead;
+
     let mut x = String::new();
+    loop {
+        match it.next() {
+            Some((pos, '"')) if take_repeated(it, '#', hashes) => {
+                return Ok(Some(Token {
+                    kind: TokenKind::String(x),
+                    pos: FilePos {
+                        start,
+                        end: pos + 1 + hashes,
+                    },
+                    ..Default::default()
+                }));
+            }
+            Some((_, ch)) => x.push(ch),
+            None => {
+                return Err(Error {
+                    error: ErrorTypes::UnterminatedString,
+                    pos: FilePos {
+                        start,
+                        end: start + 2 + hashes + x.len(),
+                    },
+                })
+            }
+        }
+    }
+}
 
-    if let Some((pos, ch)) = it.peek() {
-        if *ch == '"' {
-            token.pos.start = *pos;
-            it.next();
-            while let Some((pos, ch)) = it.peek() {
-                if *ch == '"' {
-                    token.pos.end = *pos;
-                    token.kind = TokenKind::String(x);
+fn lex_comment(it: &mut Peekable<CharIndices>, own_line: bool) -> Result<Option<Comment>, Error> {
+    let mut ahead = it.clone();
+    let (Some((start, _)), Some((_, second))) = (ahead.next(), ahead.next()) else {
+        return Ok(None);
+    };
+    let kind = match second {
+        '/' => CommentKind::Line,
+        '*' => CommentKind::Block,
+        _ => return Ok(None),
+    };
+    *it = ahead;
+
+    let mut text = String::new();
+    let mut end = start + 2;
+    let mut terminated = kind == CommentKind::Line;
+    while let Some((pos, ch)) = it.peek().copied() {
+        end = pos;
+        match (kind, ch) {
+            (CommentKind::Line, '\n') => break,
+            (CommentKind::Block, '*') => {
+                it.next();
+                if let Some((pos, '/')) = it.peek().copied() {
                     it.next();
+                    end = pos + 1;
+                    terminated = true;
                     break;
                 }
-                x.push(*ch);
+                text.push(ch);
+                end = pos + 1;
+            }
+            _ => {
                 it.next();
+                text.push(ch);
+                end = pos + ch.len_utf8();
             }
         }
     }
 
-    token
+    if !terminated {
+        return Err(Error {
+            error: ErrorTypes::UnterminatedComment,
+            pos: FilePos { start, end },
+        });
+    }
+    if kind == CommentKind::Line {
+        text.truncate(text.trim_end().len());
+    }
+
+    Ok(Some(Comment {
+        kind,
+        text,
+        pos: FilePos { start, end },
+        own_line,
+    }))
 }
 
-pub fn tokenize(s: &str) -> Vec<Token> {
+pub fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
     let mut tokens: Vec<Token> = vec![];
+    let mut comments: Vec<Comment> = vec![];
     let mut it = s.char_indices().peekable();
     loop {
+        // comments lexed before the token that was just pushed belong to it
+        if let Some(token) = tokens.last_mut() {
+            if token.comments.is_empty()
+                && !comments.is_empty()
+                && token.pos.start >= comments[0].pos.end
+            {
+                token.comments = std::mem::take(&mut comments);
+            }
+        }
+
         skip_spaces(&mut it);
         let ch;
         let pos;
@@ -163,10 +639,20 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                     end: pos,
                 },
                 kind: TokenKind::EndOfInput,
+                comments,
             });
             break;
         }
 
+        if ch == '/' {
+            let own_line = s[..pos].trim_end_matches(|c: char| c.is_whitespace() && c != '\n');
+            let own_line = own_line.is_empty() || own_line.ends_with('\n');
+            if let Some(comment) = lex_comment(&mut it, own_line)? {
+                comments.push(comment);
+                continue;
+            }
+        }
+
         match ch {
             ';' | '\n' => {
                 it.next();
@@ -176,6 +662,7 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::Separator,
+                    ..Default::default()
                 });
                 continue;
             }
@@ -187,6 +674,7 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::Assign,
+                    ..Default::default()
                 });
                 continue;
             }
@@ -199,6 +687,7 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::LeftBrace,
+                    ..Default::default()
                 });
                 continue;
             }
@@ -211,6 +700,7 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::RightBrace,
+                    ..Default::default()
                 });
                 continue;
             }
@@ -223,6 +713,7 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::LeftBracket,
+                    ..Default::default()
                 });
                 continue;
             }
@@ -235,12 +726,13 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::RightBracket,
+                    ..Default::default()
                 });
                 continue;
             }
 
             '"' => {
-                tokens.push(lex_string(&mut it));
+                tokens.push(lex_string(&mut it)?);
                 continue;
             }
             '.' => {
@@ -251,6 +743,43 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::Dot,
+                    ..Default::default()
+                });
+                continue;
+            }
+            '(' => {
+                it.next();
+                tokens.push(Token {
+                    pos: FilePos {
+                        start: pos,
+                        end: pos + 1,
+                    },
+                    kind: TokenKind::LeftParen,
+                    ..Default::default()
+                });
+                continue;
+            }
+            ')' => {
+                it.next();
+                tokens.push(Token {
+                    pos: FilePos {
+                        start: pos,
+                        end: pos + 1,
+                    },
+                    kind: TokenKind::RightParen,
+                    ..Default::default()
+                });
+                continue;
+            }
+            ',' => {
+                it.next();
+                tokens.push(Token {
+                    pos: FilePos {
+                        start: pos,
+                        end: pos + 1,
+                    },
+                    kind: TokenKind::Comma,
+                    ..Default::default()
                 });
                 continue;
             }
@@ -263,6 +792,7 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::ValueCall,
+                    ..Default::default()
                 });
                 continue;
             }
@@ -275,12 +805,21 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                         end: pos + 1,
                     },
                     kind: TokenKind::RecordCall,
+                    ..Default::default()
                 });
                 continue;
             }
 
-            _ if ch.is_ascii_digit() => {
-                tokens.push(lex_number(&mut it));
+            _ if ch.is_ascii_digit() || ch == '-' => {
+                tokens.push(lex_number(&mut it, s.len())?);
+                continue;
+            }
+            'r' => {
+                if let Some(token) = lex_raw_string(&mut it)? {
+                    tokens.push(token);
+                } else {
+                    tokens.push(lex_ident(&mut it));
+                }
                 continue;
             }
             _ if ch.is_alphanumeric() => {
@@ -288,11 +827,19 @@ pub fn tokenize(s: &str) -> Vec<Token> {
                 continue;
             }
 
-            _ => unimplemented!("lexer doesn't know how to handle: {}", ch),
+            _ => {
+                return Err(Error {
+                    error: ErrorTypes::InvalidCharacter(ch),
+                    pos: FilePos {
+                        start: pos,
+                        end: pos + ch.len_utf8(),
+                    },
+                })
+            }
         }
     }
 
-    tokens
+    Ok(tokens)
 }
 
 #[cfg(test)]
@@ -306,10 +853,25 @@ mod tests {
         assert_eq!(it.map(|x| x.1).collect::<String>(), "word");
     }
 
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("meta-lang"));
+        assert!(is_identifier("snake_case2"));
+        assert!(!is_identifier("2fast"));
+        assert!(!is_identifier("_private"));
+        assert!(!is_identifier("with space"));
+        assert!(!is_identifier("null"));
+        assert!(is_identifier("nullable"));
+        assert!(!is_identifier(""));
+    }
+
     #[test]
     fn lexing_number() {
         let mut it = "2023".char_indices().peekable();
-        assert_eq!(lex_number(&mut it).kind, TokenKind::Number(2023));
+        assert_eq!(
+            lex_number(&mut it, 4).unwrap().kind,
+            TokenKind::Number(2023)
+        );
     }
 
     #[test]
@@ -324,7 +886,7 @@ mod tests {
     fn lexing_string() {
         let mut it = "\"some\"".char_indices().peekable();
         assert_eq!(
-            lex_string(&mut it).kind,
+            lex_string(&mut it).unwrap().kind,
             TokenKind::String("some".to_string())
         );
     }
@@ -333,6 +895,7 @@ mod tests {
     fn tokenize_braces() {
         assert_eq!(
             tokenize("{}")
+                .unwrap()
                 .iter()
                 .map(|t| t.kind.clone())
                 .collect::<Vec<TokenKind>>(),
@@ -348,6 +911,7 @@ mod tests {
     fn tokenize_value_call() {
         assert_eq!(
             tokenize("@")
+                .unwrap()
                 .iter()
                 .map(|t| t.kind.clone())
                 .collect::<Vec<TokenKind>>(),
@@ -359,6 +923,7 @@ mod tests {
     fn tokenize_record_call() {
         assert_eq!(
             tokenize("#")
+                .unwrap()
                 .iter()
                 .map(|t| t.kind.clone())
                 .collect::<Vec<TokenKind>>(),
@@ -372,6 +937,7 @@ mod tests {
 
         assert_eq!(
             tokenize(data)
+                .unwrap()
                 .iter()
                 .map(|t| t.kind.clone())
                 .collect::<Vec<TokenKind>>(),
@@ -390,6 +956,7 @@ mod tests {
 
         assert_eq!(
             tokenize(data)
+                .unwrap()
                 .iter()
                 .map(|t| t.kind.clone())
                 .collect::<Vec<TokenKind>>(),
@@ -416,6 +983,7 @@ mod tests {
 
         assert_eq!(
             tokenize(data)
+                .unwrap()
                 .iter()
                 .map(|t| t.kind.clone())
                 .collect::<Vec<TokenKind>>(),
@@ -431,4 +999,294 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn commas() {
+        let data = "[1,2 ,\n]";
+
+        assert_eq!(
+            tokenize(data)
+                .unwrap()
+                .iter()
+                .map(|t| t.kind.clone())
+                .collect::<Vec<TokenKind>>(),
+            [
+                TokenKind::LeftBracket,
+                TokenKind::Number(1),
+                TokenKind::Comma,
+                TokenKind::Number(2),
+                TokenKind::Comma,
+                TokenKind::Separator,
+                TokenKind::RightBracket,
+                TokenKind::EndOfInput,
+            ]
+        )
+    }
+
+    #[test]
+    fn parens() {
+        let data = "@f(1, a=2)";
+
+        assert_eq!(
+            tokenize(data)
+                .unwrap()
+                .iter()
+                .map(|t| t.kind.clone())
+                .collect::<Vec<TokenKind>>(),
+            [
+                TokenKind::ValueCall,
+                TokenKind::ID("f".to_string()),
+                TokenKind::LeftParen,
+                TokenKind::Number(1),
+                TokenKind::Comma,
+                TokenKind::ID("a".to_string()),
+                TokenKind::Assign,
+                TokenKind::Number(2),
+                TokenKind::RightParen,
+                TokenKind::EndOfInput,
+            ]
+        )
+    }
+
+    #[test]
+    fn comments_are_attached_to_the_next_token() {
+        let tokens = tokenize("// leading\nx = 2 /* inline */ // trailing\n").unwrap();
+
+        assert_eq!(
+            tokens.iter().map(|t| t.kind.clone()).collect::<Vec<_>>(),
+            [
+                TokenKind::Separator,
+                TokenKind::ID("x".to_string()),
+                TokenKind::Assign,
+                TokenKind::Number(2),
+                TokenKind::Separator,
+                TokenKind::EndOfInput,
+            ]
+        );
+        assert_eq!(
+            tokens[0].comments,
+            [Comment {
+                kind: CommentKind::Line,
+                text: " leading".to_string(),
+                pos: FilePos { start: 0, end: 10 },
+                own_line: true,
+            }]
+        );
+        assert_eq!(
+            tokens[4].comments,
+            [
+                Comment {
+                    kind: CommentKind::Block,
+                    text: " inline ".to_string(),
+                    pos: FilePos { start: 17, end: 29 },
+                    own_line: false,
+                },
+                Comment {
+                    kind: CommentKind::Line,
+                    text: " trailing".to_string(),
+                    pos: FilePos { start: 30, end: 41 },
+                    own_line: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_at_end_of_input() {
+        let tokens = tokenize("x = 1\n/* multi\n * line */").unwrap();
+        let last = tokens.last().unwrap();
+        assert_eq!(last.kind, TokenKind::EndOfInput);
+        assert_eq!(last.comments.len(), 1);
+        assert_eq!(last.comments[0].text, " multi\n * line ");
+        assert!(last.comments[0].own_line);
+    }
+
+    fn string(s: &str) -> Result<TokenKind, Error> {
+        let mut tokens = tokenize(s)?;
+        Ok(tokens.remove(0).kind)
+    }
+
+    #[test]
+    fn string_escapes() -> Result<(), Error> {
+        assert_eq!(
+            string(r#""say \"hi\"\\ \n\t\u{1F600}""#)?,
+            TokenKind::String("say \"hi\"\\ \n\t\u{1F600}".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn raw_strings() -> Result<(), Error> {
+        assert_eq!(
+            string(r#"r"C:\path""#)?,
+            TokenKind::String("C:\\path".to_string())
+        );
+        assert_eq!(
+            string(r###"r##"a "# b"##"###)?,
+            TokenKind::String("a \"# b".to_string())
+        );
+        assert_eq!(
+            tokenize("r = 1").unwrap()[0].kind,
+            TokenKind::ID("r".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn multi_line_strings() -> Result<(), Error> {
+        assert_eq!(
+            string("\"\"\"\n        first\n          second \\\"\"\"\n        \"\"\"")?,
+            TokenKind::String("first\n  second \"\"\"\n".to_string())
+        );
+        assert_eq!(
+            string("\"\"\"one \"quoted\" line\"\"\"")?,
+            TokenKind::String("one \"quoted\" line".to_string())
+        );
+        assert_eq!(
+            string("\"\"\"\n    a\n  b\"\"\"")?,
+            TokenKind::String("  a\nb".to_string())
+        );
+        assert_eq!(
+            string("\"\"\"\n    a\n  \"\"\"")?,
+            TokenKind::String("  a\n".to_string())
+        );
+        assert_eq!(
+            string("\"\"\"\n    a\n\"\"\"")?,
+            TokenKind::String("    a\n".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn string_errors() {
+        let error = |s| tokenize(s).unwrap_err();
+
+        assert_eq!(
+            error("x = \"abc"),
+            Error {
+                error: ErrorTypes::UnterminatedString,
+                pos: FilePos { start: 4, end: 8 },
+            }
+        );
+        assert_eq!(
+            error("x = \"a\\qb\""),
+            Error {
+                error: ErrorTypes::InvalidEscape,
+                pos: FilePos { start: 6, end: 8 },
+            }
+        );
+        assert_eq!(
+            error("\"\\u{110000}\"").error,
+            ErrorTypes::InvalidUnicodeEscape
+        );
+        assert_eq!(error("\"\\u{zz}\"").error, ErrorTypes::InvalidEscape);
+        assert_eq!(error("r#\"abc\"").error, ErrorTypes::UnterminatedString);
+        assert_eq!(error("\"\"\"abc\"\"").error, ErrorTypes::UnterminatedString);
+        assert_eq!(error("/* abc").error, ErrorTypes::UnterminatedComment);
+    }
+
+    #[test]
+    fn number_at_end_of_input() -> Result<(), Error> {
+        let tokens = tokenize("x = 0")?;
+        assert_eq!(tokens[2].kind, TokenKind::Number(0));
+        assert_eq!(tokens[2].pos, FilePos { start: 4, end: 5 });
+
+        let tokens = tokenize("x = 120")?;
+        assert_eq!(tokens[2].pos, FilePos { start: 4, end: 7 });
+        Ok(())
+    }
+
+    #[test]
+    fn lexer_errors() {
+        assert_eq!(
+            tokenize("x = 9999999999999999999").unwrap_err(),
+            Error {
+                error: ErrorTypes::IntegerOverflow,
+                pos: FilePos { start: 4, end: 23 },
+            }
+        );
+        assert_eq!(
+            tokenize("x = 1\ny = $").unwrap_err(),
+            Error {
+                error: ErrorTypes::InvalidCharacter('$'),
+                pos: FilePos { start: 10, end: 11 },
+            }
+        );
+        assert_eq!(
+            tokenize("x = 1 / 2").unwrap_err().error,
+            ErrorTypes::InvalidCharacter('/')
+        );
+        assert_eq!(
+            tokenize("x = \u{e9}\u{2603}").unwrap_err().pos,
+            FilePos { start: 6, end: 9 }
+        );
+    }
+
+    fn number(s: &str) -> Result<TokenKind, Error> {
+        let mut tokens = tokenize(s)?;
+        assert_eq!(tokens.len(), 2, "{s} lexed into {tokens:?}");
+        Ok(tokens.remove(0).kind)
+    }
+
+    #[test]
+    fn number_literals() -> Result<(), Error> {
+        assert_eq!(number("-3")?, TokenKind::Number(-3));
+        assert_eq!(number("1_000_000")?, TokenKind::Number(1_000_000));
+        assert_eq!(number("0xFF")?, TokenKind::Number(255));
+        assert_eq!(number("-0x10")?, TokenKind::Number(-16));
+        assert_eq!(number("0o17")?, TokenKind::Number(15));
+        assert_eq!(number("0b1010")?, TokenKind::Number(10));
+        assert_eq!(number("9223372036854775807")?, TokenKind::Number(i64::MAX));
+        assert_eq!(number("-9223372036854775808")?, TokenKind::Number(i64::MIN));
+        assert_eq!(number("1.05")?, TokenKind::Float(1.05));
+        assert_eq!(number("-0.5")?, TokenKind::Float(-0.5));
+        assert_eq!(number("1e-9")?, TokenKind::Float(1e-9));
+        assert_eq!(number("2.5E+3")?, TokenKind::Float(2500.0));
+        assert_eq!(number("1_0.0_1")?, TokenKind::Float(10.01));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_number_literals() {
+        let error = |s| tokenize(s).unwrap_err().error;
+
+        assert_eq!(error("9223372036854775808"), ErrorTypes::IntegerOverflow);
+        assert_eq!(error("0xFFFFFFFFFFFFFFFFF"), ErrorTypes::IntegerOverflow);
+        assert_eq!(error("1e999"), ErrorTypes::FloatOverflow);
+        assert_eq!(error("12abc"), ErrorTypes::InvalidNumber);
+        assert_eq!(error("0xZZ"), ErrorTypes::InvalidNumber);
+        assert_eq!(error("0b102"), ErrorTypes::InvalidNumber);
+        assert_eq!(error("1."), ErrorTypes::InvalidNumber);
+        assert_eq!(error("1.2.3"), ErrorTypes::InvalidNumber);
+        assert_eq!(error("1__0"), ErrorTypes::InvalidNumber);
+        assert_eq!(error("1_"), ErrorTypes::InvalidNumber);
+        assert_eq!(error("1e"), ErrorTypes::InvalidNumber);
+        assert_eq!(error("- 1"), ErrorTypes::InvalidNumber);
+        assert_eq!(
+            tokenize("x = 12abc").unwrap_err().pos,
+            FilePos { start: 4, end: 9 }
+        );
+        assert_eq!(
+            tokenize("x = 1e").unwrap_err().pos,
+            FilePos { start: 6, end: 6 }
+        );
+    }
+
+    #[test]
+    fn keywords() -> Result<(), Error> {
+        assert_eq!(
+            tokenize("true false null truth")?
+                .iter()
+                .map(|t| t.kind.clone())
+                .collect::<Vec<TokenKind>>(),
+            [
+                TokenKind::Bool(true),
+                TokenKind::Bool(false),
+                TokenKind::Null,
+                TokenKind::ID("truth".to_string()),
+                TokenKind::EndOfInput,
+            ]
+        );
+        Ok(())
+    }
 }
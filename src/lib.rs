@@ -1,10 +1,18 @@
 pub mod ast;
+pub mod convert;
+pub mod diagnostics;
 pub mod eval;
 pub mod goodies;
 pub mod lexer;
+pub mod merge;
 pub mod parser;
+pub mod printer;
+pub mod registry;
+pub mod serde;
 
-type Version = f32;
+pub use crate::serde::{from_str, to_string};
+
+type Version = f64;
 
 // TODO: change this to some type supporting semver
 const VERSION: Version = 1.0;
@@ -2,24 +2,32 @@
 #![allow(clippy::cargo_common_metadata)]
 
 use alt::ast::Record;
+use alt::ast::Typed;
 use alt::ast::Value;
+use alt::diagnostics::{self, Diagnostic};
 use alt::eval;
 use alt::eval::Evaluator;
 use alt::goodies;
 use alt::lexer;
+use alt::merge::MergePolicy;
 use alt::parser;
-use alt::parser::parse;
+use alt::printer;
+use alt::registry::Registry;
 use std::fmt::Display;
+use std::fs;
 use std::io;
 use std::io::BufRead;
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitCode;
 
 #[derive(Debug)]
 enum Error {
     Parse(parser::Error),
     Eval(eval::Error),
     SerdeJson(serde_json::Error),
-    NotName,
-    NotNumber,
+    Print(printer::Error),
+    Io(io::Error),
 }
 
 impl Display for Error {
@@ -28,8 +36,8 @@ impl Display for Error {
             Self::Parse(err) => write!(f, "parser error: {err}"),
             Self::Eval(err) => write!(f, "evaluation error: {err}"),
             Self::SerdeJson(err) => write!(f, "serde_json error: {err}"),
-            Self::NotName => write!(f, "not a possible cat name"),
-            Self::NotNumber => write!(f, "not a number"),
+            Self::Print(err) => write!(f, "printing error: {err}"),
+            Self::Io(err) => write!(f, "io error: {err}"),
         }
     }
 }
@@ -42,6 +50,12 @@ impl From<parser::Error> for Error {
     }
 }
 
+impl From<lexer::Error> for Error {
+    fn from(value: lexer::Error) -> Self {
+        Self::Parse(value.into())
+    }
+}
+
 impl From<eval::Error> for Error {
     fn from(value: eval::Error) -> Self {
         Self::Eval(value)
@@ -54,104 +68,179 @@ impl From<serde_json::Error> for Error {
     }
 }
 
-struct T {
-    good_evaluator: goodies::Evaluator,
+impl From<printer::Error> for Error {
+    fn from(value: printer::Error) -> Self {
+        Self::Print(value)
+    }
 }
 
-impl T {
-    fn call(x: &Value) -> Result<Value, eval::Error> {
-        match x {
-            Value::String(s) => {
-                let result = s.parse::<i32>();
-                match result {
-                    Ok(num) => Ok(Value::Number(num)),
-                    Err(err) => Err(eval::Error::Eval(Box::new(err))),
-                }
-            }
-            _ => Ok(x.clone()),
-        }
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
     }
-    fn pisoi(x: &Value) -> Result<Value, eval::Error> {
+}
+
+struct T;
+
+impl T {
+    fn call(x: Value) -> Result<Value, eval::Error> {
         match x {
-            Value::String(_) => Ok(Value::Typed(alt::ast::Typed {
-                kind: "pisoi".to_string(),
-                value: Box::new(x.clone()),
-            })),
-            _ => Err(eval::Error::Eval(Box::new(Error::NotName))),
+            Value::String(s) => s
+                .parse::<i64>()
+                .map(Value::Number)
+                .map_err(|err| eval::Error::Eval(Box::new(err))),
+            _ => Ok(x),
         }
     }
-    fn itoa(x: &Value) -> Result<Value, eval::Error> {
-        match x {
-            Value::Number(n) => Ok(Value::String(n.to_string())),
-            _ => Err(eval::Error::Eval(Box::new(Error::NotNumber))),
+
+    fn pisoi(name: String) -> Typed {
+        Typed {
+            kind: "pisoi".to_string(),
+            value: Box::new(Value::String(name)),
         }
     }
 
-    fn pisoi_record(x: &Value) -> Result<Option<Record>, eval::Error> {
-        match x {
-            Value::String(s) => Ok(Some(alt::ast::Record {
-                id: s.clone(),
-                value: Value::Typed(alt::ast::Typed {
-                    kind: "pisoi".to_string(),
-                    value: Box::new(x.clone()),
+    fn registry() -> Registry {
+        goodies::registry().extend(
+            Registry::new()
+                .value("call", Self::call)
+                .value("pisoi", |name| Ok(Self::pisoi(name)))
+                .value("itoa", |n: i64| Ok(n.to_string()))
+                .record("pisoi", |name: String| {
+                    Ok(Some(Record {
+                        id: name.clone(),
+                        value: Value::Typed(Self::pisoi(name)),
+                        ..Default::default()
+                    }))
                 }),
-            })),
-            _ => Err(eval::Error::Eval(Box::new(Error::NotName))),
-        }
+        )
     }
 }
 
-impl Evaluator for T {
-    fn value_function_eval(&mut self, call: &alt::ast::Call) -> Result<Value, eval::Error> {
-        match call.function.as_str() {
-            "call" => Self::call(&call.value),
-            "pisoi" => Self::pisoi(&call.value),
-            "itoa" => Self::itoa(&call.value),
-            _ => self.good_evaluator.value_function_eval(call),
-        }
-    }
+fn report<'a, E>(s: &str, name: &str, e: &'a E)
+where
+    Diagnostic: From<&'a E>,
+{
+    eprint!("{}", Diagnostic::from(e).render(s, name));
+}
 
-    fn record_function_eval(
-        &mut self,
-        call: &alt::ast::Call,
-    ) -> Result<Option<Record>, eval::Error> {
-        match call.function.as_str() {
-            "pisoi" => Self::pisoi_record(&call.value),
-            _ => self.good_evaluator.record_function_eval(call),
-        }
+/// The evaluator documents are run with.
+fn evaluator() -> goodies::Evaluator {
+    goodies::Evaluator::new(T::registry())
+}
+
+/// Parses `s`, merging duplicate keys with `policy`, and reports every
+/// syntax error in it. `None` when there were any.
+fn parse_source(s: &str, name: &str, policy: MergePolicy) -> Option<(Vec<lexer::Token>, Value)> {
+    let tokens = lexer::tokenize(s)
+        .inspect_err(|e| report(s, name, e))
+        .ok()?;
+    let (object, errors) = parser::parse_recovering(&tokens, policy);
+    for e in &errors {
+        report(s, name, e);
     }
+    errors.is_empty().then_some((tokens, object))
 }
-fn main() -> Result<(), Error> {
-    println!("Hello, world!");
 
-    let stdin = io::stdin();
-    let s = stdin
-        .lock()
-        .lines()
-        .map(|x| x.unwrap() + "\n")
-        .collect::<Vec<String>>()
-        .concat();
-    let tokens = lexer::tokenize(&s);
-    println!("I parsed:");
+/// `s` in canonical form, or `None` when the errors that prevent
+/// formatting it were reported.
+fn format_source(s: &str, name: &str) -> Result<Option<String>, Error> {
+    // the document is formatted as it is evaluated
+    let Some((tokens, object)) = parse_source(s, name, evaluator().merge_policy()) else {
+        return Ok(None);
+    };
+    if let Err(e) = printer::check_comments(&tokens, &object) {
+        report(s, name, &e);
+        return Ok(None);
+    }
+    Ok(Some(printer::print(&object)?))
+}
 
-    let object = parse(&tokens).map_err(|e: parser::Error| {
-        if s.lines().count() < 2 {
-            println!("Syntax error:");
-            println!("\t{e}");
+/// `alt fmt [--check] [FILE]...`
+///
+/// Rewrites every file in canonical form, or with `--check` only reports the
+/// files that are not formatted. Without files, formats stdin to stdout.
+/// Files that cannot be formatted are reported and skipped, failing once
+/// the others are done.
+fn fmt(args: &[String]) -> Result<ExitCode, Error> {
+    let check = args.iter().any(|a| a == "--check");
+    let files = args
+        .iter()
+        .filter(|a| *a != "--check")
+        .collect::<Vec<&String>>();
 
-            return e;
+    if files.is_empty() {
+        let mut s = String::new();
+        io::stdin().read_to_string(&mut s)?;
+        let Some(formatted) = format_source(&s, "<stdin>")? else {
+            return Ok(ExitCode::FAILURE);
+        };
+        if check {
+            return Ok(if formatted == s {
+                ExitCode::SUCCESS
+            } else {
+                println!("<stdin> is not formatted");
+                ExitCode::FAILURE
+            });
         }
-        let (left, _) = s.split_at(e.pos.start);
-        let line = left.lines().count() - 1;
-        if let Some(l) = s.lines().nth(line) {
-            println!("Syntax error:");
-            println!("\t{l}");
+        print!("{formatted}");
+        return Ok(ExitCode::SUCCESS);
+    }
 
-            println!("\t{}^ {e}", " ".repeat(e.pos.start));
+    let mut code = ExitCode::SUCCESS;
+    for file in files {
+        let s = match fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: cannot read {file}: {e}");
+                code = ExitCode::FAILURE;
+                continue;
+            }
+        };
+        let Some(formatted) = format_source(&s, file)? else {
+            code = ExitCode::FAILURE;
+            continue;
+        };
+        if formatted == s {
+            continue;
+        }
+        if check {
+            println!("{file} is not formatted");
+            code = ExitCode::FAILURE;
+        } else {
+            fs::write(file, formatted)?;
         }
+    }
+    Ok(code)
+}
+
+fn main() -> Result<ExitCode, Error> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.first().map(String::as_str) == Some("fmt") {
+        return fmt(&args[1..]);
+    }
+
+    println!("Hello, world!");
+
+    // `alt [FILE]`, reading stdin without a file
+    let (s, name) = if let Some(file) = args.first() {
+        (fs::read_to_string(file)?, file.as_str())
+    } else {
+        let stdin = io::stdin();
+        let s = stdin
+            .lock()
+            .lines()
+            .map(|x| x.unwrap() + "\n")
+            .collect::<Vec<String>>()
+            .concat();
+        (s, "<stdin>")
+    };
+    let mut ev = evaluator();
+    let Some((_, object)) = parse_source(&s, name, ev.merge_policy()) else {
+        return Ok(ExitCode::FAILURE);
+    };
+    println!("I parsed:");
 
-        e
-    })?;
     if let Value::ObjectWithCalls(ref records) = object {
         println!("{records:?}");
 
@@ -162,21 +251,21 @@ fn main() -> Result<(), Error> {
 
     println!("== Doing Eval ==");
 
-    let mut ev = T {
-        good_evaluator: goodies::Evaluator::default(),
+    let result = if args.is_empty() {
+        ev.eval(&object)
+    } else {
+        ev.eval_file(Path::new(name), &object)
     };
 
-    let result = ev.eval(&object);
-    let value;
-
     println!("{result:?}");
 
-    match result {
-        Ok(t) => value = t,
-        Err(err) => {
-            panic!("{err}")
+    let value = match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprint!("{}", diagnostics::render_eval(&e, &s, name));
+            return Ok(ExitCode::FAILURE);
         }
-    }
+    };
 
     println!("{value:?}");
 
@@ -186,5 +275,5 @@ fn main() -> Result<(), Error> {
 
     println!("{json}");
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,155 @@
+use crate::ast::{Record, Value};
+use crate::lexer::FilePos;
+use std::error::Error as StdError;
+use std::fmt;
+
+/// What to do when an object gets a second record with an id it already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Reject the document.
+    #[default]
+    Error,
+    /// Replace the earlier value, keeping the record where it first appeared.
+    LastWins,
+    /// Keep the earlier value and ignore the later one.
+    FirstWins,
+    /// Merge objects record by record, otherwise the later value wins.
+    DeepMerge,
+}
+
+/// Two records with the same id in one object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub id: String,
+    pub first: FilePos,
+    pub second: FilePos,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duplicate key {} at {}, first defined at {}",
+            self.id, self.second, self.first
+        )
+    }
+}
+
+impl StdError for Conflict {}
+
+/// Adds `record` to an evaluated object, resolving a duplicate id with
+/// `policy`.
+pub fn insert(
+    records: &mut Vec<Record>,
+    record: Record,
+    policy: MergePolicy,
+) -> Result<(), Conflict> {
+    let Some(existing) = records.iter_mut().find(|r| r.id == record.id) else {
+        records.push(record);
+        return Ok(());
+    };
+
+    match policy {
+        MergePolicy::Error => Err(Conflict {
+            id: record.id,
+            first: existing.pos,
+            second: record.pos,
+        }),
+        MergePolicy::FirstWins => Ok(()),
+        MergePolicy::LastWins => {
+            *existing = record;
+            Ok(())
+        }
+        MergePolicy::DeepMerge => match (&mut existing.value, record.value) {
+            (Value::Object(inner), Value::Object(new)) => {
+                for record in new {
+                    insert(inner, record, policy)?;
+                }
+                Ok(())
+            }
+            (_, value) => {
+                existing.value = value;
+                existing.pos = record.pos;
+                Ok(())
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, value: Value, start: usize) -> Record {
+        Record {
+            id: id.to_string(),
+            value,
+            pos: FilePos {
+                start,
+                end: start + id.len(),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn object(records: Vec<Record>) -> Value {
+        Value::Object(records)
+    }
+
+    #[test]
+    fn policies() {
+        let first = || {
+            vec![record(
+                "a",
+                object(vec![record("x", Value::Number(1), 5)]),
+                0,
+            )]
+        };
+        let second = || record("a", object(vec![record("y", Value::Number(2), 15)]), 10);
+
+        let mut records = first();
+        assert_eq!(
+            insert(&mut records, second(), MergePolicy::Error),
+            Err(Conflict {
+                id: "a".to_string(),
+                first: FilePos { start: 0, end: 1 },
+                second: FilePos { start: 10, end: 11 },
+            })
+        );
+
+        let mut records = first();
+        insert(&mut records, second(), MergePolicy::FirstWins).unwrap();
+        assert_eq!(records, first());
+
+        let mut records = first();
+        insert(&mut records, second(), MergePolicy::LastWins).unwrap();
+        assert_eq!(records, [second()]);
+
+        let mut records = first();
+        insert(&mut records, second(), MergePolicy::DeepMerge).unwrap();
+        assert_eq!(
+            records,
+            [record(
+                "a",
+                object(vec![
+                    record("x", Value::Number(1), 5),
+                    record("y", Value::Number(2), 15),
+                ]),
+                0,
+            )]
+        );
+    }
+
+    #[test]
+    fn deep_merge_replaces_values() {
+        let mut records = vec![record("a", Value::Number(1), 0)];
+        insert(
+            &mut records,
+            record("a", object(Vec::new()), 10),
+            MergePolicy::DeepMerge,
+        )
+        .unwrap();
+        assert_eq!(records, [record("a", object(Vec::new()), 10)]);
+        assert_eq!(records[0].pos.start, 10);
+    }
+}
@@ -1,30 +1,164 @@
-use crate::ast::{Call, Record, RecordOrCall, Value};
-use crate::lexer::{self, FilePos};
+use crate::ast::{Argument, Call, Element, Record, RecordOrCall, Reference, Value};
+use crate::lexer::{self, Comment, FilePos, TokenKind};
+use crate::merge::MergePolicy;
 use std::error::Error as StdError;
 use std::fmt;
 use std::iter::Peekable;
+use std::mem;
 
 #[derive(Debug, Clone)]
 pub enum ErrorTypes {
-    EndOfInput,
-    ExpectedIdentifier,
-    ExpectedValue,
-    ExpectedAssign,
-    ExpectedNumber,
+    Lex(lexer::ErrorTypes),
+    /// A token that can't appear where it was found.
+    Unexpected {
+        found: TokenKind,
+        expected: Vec<TokenKind>,
+        within: Within,
+    },
+    /// A value is missing, e.g. `x =` at the end of a line.
+    MissingValue(Within),
+    /// A `{` or `[` that is never closed.
+    Unclosed {
+        close: TokenKind,
+        opened: FilePos,
+    },
+    ConflictingKey(String),
+    DuplicateKey {
+        key: String,
+        first: FilePos,
+    },
+    /// A call passing the same named argument twice.
+    DuplicateArgument {
+        name: String,
+        first: FilePos,
+    },
+    /// Objects, arrays, calls and dotted keys nested more than
+    /// [`MAX_DEPTH`] levels deep.
+    TooDeep,
 }
 
 impl fmt::Display for ErrorTypes {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Self::EndOfInput => write!(f, "reached end of input while expecting more"),
-            Self::ExpectedIdentifier => write!(f, "expected identifier"),
-            Self::ExpectedValue => write!(f, "expected value"),
-            Self::ExpectedAssign => write!(f, "expected assignment"),
-            Self::ExpectedNumber => write!(f, "expected number"),
+        match self {
+            Self::Lex(e) => write!(f, "{e}"),
+            Self::Unexpected {
+                found,
+                expected,
+                within,
+            } => {
+                write!(f, "unexpected {found} {within}")?;
+                if !expected.is_empty() {
+                    write!(f, ", expected {}", describe(expected))?;
+                }
+                Ok(())
+            }
+            Self::MissingValue(within) => write!(f, "missing value {within}"),
+            Self::Unclosed { close, .. } => {
+                write!(f, "expected {close} to close {}", closes(close))
+            }
+            Self::ConflictingKey(key) => {
+                write!(f, "{key} is used both as an object and as a value")
+            }
+            Self::DuplicateKey { key, .. } => write!(f, "duplicate key {key}"),
+            Self::DuplicateArgument { name, .. } => {
+                write!(f, "argument {name} is passed twice")
+            }
+            Self::TooDeep => write!(f, "nested more than {MAX_DEPTH} levels deep"),
+        }
+    }
+}
+
+/// Where in the document the parser was when it found an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Within {
+    Document,
+    /// Inside the object opened at the given position.
+    Object(FilePos),
+    /// Inside the array opened at the given position.
+    Array(FilePos),
+    /// Inside the argument list opened at the given position.
+    Arguments(FilePos),
+    /// After the part of a key read so far, e.g. `server.`.
+    Key(String),
+    /// After the `=` of the record with the given key.
+    Record(String),
+    /// After a call's sigil and, if read, its function name.
+    Call(String),
+}
+
+impl Within {
+    /// The opening delimiter of the enclosing object or array.
+    pub const fn opened(&self) -> Option<FilePos> {
+        match self {
+            Self::Object(pos) | Self::Array(pos) | Self::Arguments(pos) => Some(*pos),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Within {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Document => write!(f, "at the top level"),
+            Self::Object(_) => write!(f, "in object"),
+            Self::Array(_) => write!(f, "in array"),
+            Self::Arguments(_) => write!(f, "in arguments"),
+            Self::Key(key) | Self::Call(key) => write!(f, "after `{key}`"),
+            Self::Record(key) => write!(f, "after `{key} =`"),
         }
     }
 }
 
+/// The tokens a value can start with.
+fn value_start() -> [TokenKind; 8] {
+    [
+        TokenKind::Number(0),
+        TokenKind::Float(0.0),
+        TokenKind::String(String::new()),
+        TokenKind::Bool(false),
+        TokenKind::Null,
+        TokenKind::LeftBrace,
+        TokenKind::LeftBracket,
+        TokenKind::ValueCall,
+    ]
+}
+
+fn starts_value(kind: &TokenKind) -> bool {
+    value_start()
+        .iter()
+        .any(|k| mem::discriminant(k) == mem::discriminant(kind))
+}
+
+/// What a closing delimiter ends, e.g. "array" for `]`.
+pub fn closes(close: &TokenKind) -> &'static str {
+    match close {
+        TokenKind::RightBracket => "array",
+        TokenKind::RightParen => "argument list",
+        _ => "object",
+    }
+}
+
+/// Lists `expected` for an error message, e.g. "value or `]`".
+pub fn describe(expected: &[TokenKind]) -> String {
+    let any_value = value_start().iter().all(|k| expected.contains(k));
+    let mut names = Vec::new();
+    if any_value {
+        names.push("value".to_string());
+    }
+    names.extend(
+        expected
+            .iter()
+            .filter(|k| !(any_value && starts_value(k)))
+            .map(ToString::to_string),
+    );
+
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {last}", rest.join(", ")),
+        None => String::new(),
+    }
+}
+
 #[derive(Debug)]
 pub struct Error {
     pub error: ErrorTypes,
@@ -39,226 +173,835 @@ impl fmt::Display for Error {
 
 impl StdError for Error {}
 
-fn parse_value<'a, T>(it: &mut Peekable<T>) -> Result<Value, Error>
+impl From<lexer::Error> for Error {
+    fn from(value: lexer::Error) -> Self {
+        Self {
+            error: ErrorTypes::Lex(value.error),
+            pos: value.pos,
+        }
+    }
+}
+
+/// How deep values may nest, so that parsing, and everything walking the
+/// document after it, cannot run out of stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// State shared by the parse functions.
+#[derive(Default)]
+struct Context {
+    policy: MergePolicy,
+    /// Errors the parser recovered from.
+    errors: Vec<Error>,
+    /// Where the input ends, for errors found after the last token.
+    eof: FilePos,
+    /// How many objects, arrays and calls enclose the current token.
+    depth: usize,
+    /// Whether recovering from an error skipped to the end of input, after
+    /// which every enclosing object, array and argument list is unclosed.
+    skipped_to_end: bool,
+}
+
+impl Context {
+    /// The next token's kind and position, the end of input once the tokens
+    /// run out.
+    fn peek<'a, T>(&self, it: &mut Peekable<T>) -> (&'a TokenKind, FilePos)
+    where
+        T: Iterator<Item = &'a lexer::Token>,
+    {
+        match it.peek() {
+            Some(token) => (&token.kind, token.pos),
+            None => (&TokenKind::EndOfInput, self.eof),
+        }
+    }
+
+    /// Records an error to recover from. A delimiter left unclosed by an
+    /// earlier error is not reported again, nor are the ones enclosing it.
+    fn report(&mut self, e: Error) {
+        if matches!(e.error, ErrorTypes::Unclosed { .. }) {
+            if self.skipped_to_end {
+                return;
+            }
+            self.skipped_to_end = true;
+        }
+        self.errors.push(e);
+    }
+
+    /// Runs `parse` one level deeper, failing at `pos` past [`MAX_DEPTH`].
+    fn nested<R>(
+        &mut self,
+        pos: FilePos,
+        parse: impl FnOnce(&mut Self) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        if self.depth >= MAX_DEPTH {
+            return Err(Error {
+                error: ErrorTypes::TooDeep,
+                pos,
+            });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+}
+
+fn unexpected(found: &TokenKind, pos: FilePos, expected: Vec<TokenKind>, within: Within) -> Error {
+    Error {
+        error: ErrorTypes::Unexpected {
+            found: found.clone(),
+            expected,
+            within,
+        },
+        pos,
+    }
+}
+
+fn parse_value<'a, T>(
+    it: &mut Peekable<T>,
+    ctx: &mut Context,
+    within: &Within,
+) -> Result<Value, Error>
 where
     T: Iterator<Item = &'a lexer::Token>,
 {
-    match it.peek() {
-        None => Err(Error {
-            error: ErrorTypes::EndOfInput,
-            pos: Default::default(),
+    let (kind, pos) = ctx.peek(it);
+    match kind {
+        TokenKind::Number(num) => {
+            it.next();
+            Ok(Value::Number(*num))
+        }
+        TokenKind::Float(num) => {
+            it.next();
+            Ok(Value::Float(*num))
+        }
+        TokenKind::Bool(b) => {
+            it.next();
+            Ok(Value::Bool(*b))
+        }
+        TokenKind::Null => {
+            it.next();
+            Ok(Value::Null)
+        }
+        TokenKind::String(s) => {
+            it.next();
+            Ok(Value::String(s.clone()))
+        }
+        // the opening delimiter is only taken once the depth allows it, so
+        // recovery skips it as a whole
+        TokenKind::LeftBrace => ctx.nested(pos, |ctx| {
+            it.next();
+            let records = parse_multiple_records(it, &Within::Object(pos), ctx);
+            it.next();
+            Ok(Value::ObjectWithCalls(records))
         }),
-        Some(token) => match &token.kind {
-            lexer::TokenKind::Number(num) => {
-                it.next();
-                if let Some(token) = it.peek() {
-                    if token.kind == lexer::TokenKind::Dot {
-                        let token = *token;
-                        it.next();
-                        if let Some(token) = it.peek() {
-                            match token.kind {
-                                lexer::TokenKind::Number(n) => {
-                                    // see https://stackoverflow.com/a/69298721
-                                    let decimals = n.checked_ilog10().unwrap_or(0) + 1;
-
-                                    let x = 10.0_f32
-                                        .powi(-(decimals as i32))
-                                        .mul_add(n as f32, *num as f32);
-                                    return Ok(Value::Float(x));
-                                }
-                                _ => {
-                                    return Err(Error {
-                                        error: ErrorTypes::ExpectedNumber,
-                                        pos: token.pos,
-                                    });
-                                }
-                            }
-                        }
-                        return Err(Error {
-                            error: ErrorTypes::EndOfInput,
-                            pos: token.pos,
-                        });
-                    }
+        TokenKind::LeftBracket => ctx.nested(pos, |ctx| {
+            it.next();
+            let values = parse_multiple_values(it, pos, ctx);
+            it.next();
+            Ok(Value::Array(values))
+        }),
+        TokenKind::ValueCall => {
+            it.next();
+            if let (TokenKind::ID(id), _) = ctx.peek(it) {
+                if id == REF {
+                    it.next();
+                    return parse_reference(it, pos, ctx);
                 }
-                Ok(Value::Number(*num))
             }
-            lexer::TokenKind::String(s) => Ok(Value::String(s.clone())),
-            lexer::TokenKind::LeftBrace => {
+            let call = ctx.nested(pos, |ctx| parse_call(it, '@', pos, ctx))?;
+            Ok(Value::Call(call))
+        }
+        TokenKind::Separator
+        | TokenKind::Comma
+        | TokenKind::RightBrace
+        | TokenKind::RightBracket
+        | TokenKind::RightParen
+        | TokenKind::EndOfInput => Err(Error {
+            error: ErrorTypes::MissingValue(within.clone()),
+            pos,
+        }),
+        _ => Err(unexpected(
+            kind,
+            pos,
+            value_start().to_vec(),
+            within.clone(),
+        )),
+    }
+}
+
+/// The name after `@` that makes a reference rather than a call.
+const REF: &str = "ref";
+
+/// Parses the key following `@ref`, which starts at `pos`.
+fn parse_reference<'a, T>(
+    it: &mut Peekable<T>,
+    pos: FilePos,
+    ctx: &mut Context,
+) -> Result<Value, Error>
+where
+    T: Iterator<Item = &'a lexer::Token>,
+{
+    let (found, at) = ctx.peek(it);
+    if !matches!(found, TokenKind::ID(_)) {
+        let within = Within::Call(format!("@{REF}"));
+        return Err(unexpected(
+            found,
+            at,
+            vec![TokenKind::ID(String::new())],
+            within,
+        ));
+    }
+    let key = parse_key(it, ctx)?;
+    Ok(Value::Reference(Reference {
+        path: key.path,
+        pos: FilePos {
+            start: pos.start,
+            end: key.pos.end,
+        },
+    }))
+}
+
+/// A record key, `server.http.port` is a path of three ids.
+struct Key {
+    path: Vec<String>,
+    pos: FilePos,
+}
+
+fn parse_key<'a, T>(it: &mut Peekable<T>, ctx: &Context) -> Result<Key, Error>
+where
+    T: Iterator<Item = &'a lexer::Token>,
+{
+    let mut key = Key {
+        path: Vec::new(),
+        pos: Default::default(),
+    };
+    loop {
+        match ctx.peek(it) {
+            (TokenKind::ID(id), pos) => {
+                if key.path.is_empty() {
+                    key.pos.start = pos.start;
+                }
+                key.pos.end = pos.end;
+                key.path.push(id.clone());
                 it.next();
-                let records = parse_multiple_records(it, &lexer::TokenKind::RightBrace)?;
-                Ok(Value::ObjectWithCalls(records))
             }
-            lexer::TokenKind::LeftBracket => {
-                it.next();
-                let values = parse_multiple_values(it)?;
-                Ok(Value::Array(values))
+            (found, pos) => {
+                let within = Within::Key(key.path.join(".") + ".");
+                return Err(unexpected(
+                    found,
+                    pos,
+                    vec![TokenKind::ID(String::new())],
+                    within,
+                ));
             }
-            lexer::TokenKind::ValueCall => {
+        }
+
+        match ctx.peek(it) {
+            (TokenKind::Dot, _) => {
                 it.next();
-                let call = parse_call(it)?;
-                Ok(Value::Call(call))
             }
-            _ => Err(Error {
-                error: ErrorTypes::ExpectedValue,
-                pos: token.pos,
-            }),
-        },
+            _ => return Ok(key),
+        }
     }
 }
 
-fn parse_record<'a, T>(it: &mut Peekable<T>) -> Result<RecordOrCall, Error>
+/// Parses `key = value` and stores it in `records`, see [`insert_record`].
+/// When the value is broken the key is still stored, holding an error node.
+fn parse_record<'a, T>(
+    it: &mut Peekable<T>,
+    records: &mut Vec<RecordOrCall>,
+    ctx: &mut Context,
+) -> Result<(), Error>
 where
     T: Iterator<Item = &'a lexer::Token>,
 {
-    match it.peek() {
-        None => Err(Error {
-            error: ErrorTypes::EndOfInput,
-            pos: Default::default(),
-        }),
-        Some(token) => match &token.kind {
-            lexer::TokenKind::ID(id) => {
-                let token = *token;
-                it.next();
+    let key = parse_key(it, ctx)?;
+    // each dotted part after the first is an object of its own
+    if ctx.depth + key.path.len() > MAX_DEPTH {
+        return Err(Error {
+            error: ErrorTypes::TooDeep,
+            pos: key.pos,
+        });
+    }
+    let text = key.path.join(".");
 
-                if let Some(token) = it.peek() {
-                    if token.kind == lexer::TokenKind::Assign {
-                        it.next();
+    match ctx.peek(it) {
+        (TokenKind::Assign, _) => {
+            it.next();
+            let record = |value| Record {
+                id: key.path[key.path.len() - 1].clone(),
+                value,
+                pos: key.pos,
+                key: key.path.clone(),
+                implicit: false,
+            };
+            match parse_value(it, ctx, &Within::Record(text)) {
+                Ok(value) => insert_record(records, &key.path, record(value), &key, ctx.policy),
+                Err(e) => {
+                    let value = record(Value::Error(e.pos));
+                    if let Err(conflict) =
+                        insert_record(records, &key.path, value, &key, ctx.policy)
+                    {
+                        ctx.errors.push(conflict);
+                    }
+                    Err(e)
+                }
+            }
+        }
+        (found, pos) => Err(unexpected(
+            found,
+            pos,
+            vec![TokenKind::Assign, TokenKind::Dot],
+            Within::Key(text),
+        )),
+    }
+}
 
-                        let value = parse_value(it)?;
+/// Adds `record` at `path` inside `records`, the last id of `path` being
+/// the record's. Dotted keys merge into the object already stored under
+/// their prefix, or create an implicit one. A key that is assigned again is
+/// resolved with `policy`.
+fn insert_record(
+    records: &mut Vec<RecordOrCall>,
+    path: &[String],
+    record: Record,
+    key: &Key,
+    policy: MergePolicy,
+) -> Result<(), Error> {
+    let prefix = || key.path[..=key.path.len() - path.len()].to_vec();
+    let existing = records.iter_mut().find_map(|entry| match entry {
+        RecordOrCall::Record(existing) if Some(&existing.id) == path.first() => Some(existing),
+        _ => None,
+    });
 
-                        Ok(Record {
-                            id: id.clone(),
-                            value,
+    match (path, existing) {
+        ([_], None) => {
+            records.push(record.into());
+            Ok(())
+        }
+        ([_], Some(existing)) => match policy {
+            MergePolicy::Error => Err(Error {
+                error: ErrorTypes::DuplicateKey {
+                    key: prefix().join("."),
+                    first: existing.pos,
+                },
+                pos: key.pos,
+            }),
+            MergePolicy::FirstWins => Ok(()),
+            // a replaced record keeps its place in the document
+            MergePolicy::LastWins => {
+                *existing = Record {
+                    pos: existing.pos,
+                    ..record
+                };
+                Ok(())
+            }
+            MergePolicy::DeepMerge => match (&mut existing.value, record.value) {
+                (Value::ObjectWithCalls(inner), Value::ObjectWithCalls(new)) => {
+                    // the object is now written, not only made up by dotted keys
+                    if existing.implicit && !record.implicit {
+                        existing.implicit = false;
+                        existing.pos = record.pos;
+                        existing.key = record.key;
+                    }
+                    for entry in new {
+                        match entry {
+                            RecordOrCall::Record(record) => {
+                                let key = Key {
+                                    path: [&key.path[..], std::slice::from_ref(&record.id)]
+                                        .concat(),
+                                    pos: record.pos,
+                                };
+                                let path = [record.id.clone()];
+                                insert_record(inner, &path, record, &key, policy)?;
+                            }
+                            other => inner.push(other),
                         }
-                        .into())
-                    } else {
-                        Err(Error {
-                            error: ErrorTypes::ExpectedAssign,
-                            pos: token.pos,
-                        })
                     }
-                } else {
-                    Err(Error {
-                        error: ErrorTypes::EndOfInput,
-                        pos: token.pos,
-                    })
+                    Ok(())
                 }
-            }
+                (_, value) => {
+                    *existing = Record {
+                        value,
+                        pos: existing.pos,
+                        ..record
+                    };
+                    Ok(())
+                }
+            },
+        },
+        ([_, rest @ ..], Some(existing)) => match &mut existing.value {
+            Value::ObjectWithCalls(inner) => insert_record(inner, rest, record, key, policy),
             _ => Err(Error {
-                error: ErrorTypes::ExpectedIdentifier,
-                pos: token.pos,
+                error: ErrorTypes::ConflictingKey(prefix().join(".")),
+                pos: key.pos,
             }),
         },
+        ([id, rest @ ..], None) => {
+            let mut inner = Vec::new();
+            insert_record(&mut inner, rest, record, key, policy)?;
+            records.push(
+                Record {
+                    id: id.clone(),
+                    value: Value::ObjectWithCalls(inner),
+                    pos: key.pos,
+                    key: prefix(),
+                    implicit: true,
+                }
+                .into(),
+            );
+            Ok(())
+        }
+        ([], _) => Ok(()),
     }
 }
 
-fn parse_multiple_values<'a, T>(it: &mut Peekable<T>) -> Result<Vec<Value>, Error>
+/// Parses array elements up to and excluding the closing `]`:
+///
+/// ```text
+/// array     = "[" , { break } , [ value , { delimiter , value } , delimiter ] , "]" ;
+/// delimiter = { break } , [ "," ] , { break } ;
+/// break     = newline | ";" ;
+/// ```
+///
+/// Elements can be separated by whitespace alone, and at most one comma sits
+/// between two elements or after the last one. After an error the rest of
+/// the array is skipped and the error kept in `ctx`.
+fn parse_multiple_values<'a, T>(
+    it: &mut Peekable<T>,
+    opened: FilePos,
+    ctx: &mut Context,
+) -> Vec<Element>
 where
     T: Iterator<Item = &'a lexer::Token>,
 {
-    let mut values = Vec::new();
+    let within = Within::Array(opened);
+    let mut values: Vec<Element> = Vec::new();
+    let mut leading = Vec::new();
+    // a comma may follow each element once
+    let mut comma_allowed = false;
     loop {
-        match it.peek() {
-            None => {
-                return Err(Error {
-                    error: ErrorTypes::EndOfInput,
-                    pos: Default::default(),
+        if let Some(token) = it.peek() {
+            let trailing = values.last_mut().map(|e| &mut e.trailing);
+            sort_comments(&token.comments, trailing, &mut leading);
+        }
+
+        let result = match ctx.peek(it) {
+            (TokenKind::RightBracket, _) => break,
+            (TokenKind::Separator, _) => {
+                it.next();
+                Ok(())
+            }
+            (TokenKind::Comma, _) if comma_allowed => {
+                comma_allowed = false;
+                it.next();
+                Ok(())
+            }
+            (TokenKind::EndOfInput, pos) => Err(Error {
+                error: ErrorTypes::Unclosed {
+                    close: TokenKind::RightBracket,
+                    opened,
+                },
+                pos,
+            }),
+            (kind, pos) if starts_value(kind) => {
+                comma_allowed = true;
+                parse_value(it, ctx, &within).map(|value| {
+                    values.push(Element {
+                        value,
+                        pos,
+                        leading: mem::take(&mut leading),
+                        trailing: Vec::new(),
+                    });
                 })
             }
-            Some(token) => match token.kind {
-                lexer::TokenKind::RightBracket => {
-                    break;
+            (found, pos) => {
+                let mut expected = value_start().to_vec();
+                if comma_allowed {
+                    expected.push(TokenKind::Comma);
                 }
-                lexer::TokenKind::EndOfInput => {
-                    break;
-                }
-                lexer::TokenKind::String(_) => {
-                    values.push(parse_value(it)?);
-                    it.next();
-                }
-                _ => values.push(parse_value(it)?),
-            },
+                expected.push(TokenKind::RightBracket);
+                Err(unexpected(found, pos, expected, within.clone()))
+            }
+        };
+
+        if let Err(e) = result {
+            values.push(Value::Error(e.pos).into());
+            ctx.report(e);
+            synchronize(it, ctx, |kind| *kind == TokenKind::RightBracket);
+            break;
         }
     }
 
-    Ok(values)
+    if let Some(last) = values.last_mut() {
+        last.trailing.append(&mut leading);
+    }
+    values
 }
 
+/// Hands out the `comments` in front of the next token of an array or
+/// argument list. The ones on the line of the previous item trail it, the
+/// others lead the next item.
+fn sort_comments(
+    comments: &[Comment],
+    trailing: Option<&mut Vec<Comment>>,
+    leading: &mut Vec<Comment>,
+) {
+    let mut trailing = trailing.filter(|_| leading.is_empty());
+    for comment in comments {
+        match &mut trailing {
+            Some(trailing) if !comment.own_line => trailing.push(comment.clone()),
+            _ => {
+                trailing = None;
+                leading.push(comment.clone());
+            }
+        }
+    }
+}
+
+/// Parses records up to the end of `within`, which is either the whole
+/// document or an object. Errors inside a record are collected in `ctx` and
+/// parsing picks up again after it.
 fn parse_multiple_records<'a, T>(
     it: &mut Peekable<T>,
-    end: &lexer::TokenKind,
-) -> Result<Vec<RecordOrCall>, Error>
+    within: &Within,
+    ctx: &mut Context,
+) -> Vec<RecordOrCall>
 where
     T: Iterator<Item = &'a lexer::Token>,
 {
+    let end = match within {
+        Within::Object(_) => TokenKind::RightBrace,
+        _ => TokenKind::EndOfInput,
+    };
     let mut records = Vec::new();
     loop {
-        match it.peek() {
-            None => {
-                return Err(Error {
-                    error: ErrorTypes::EndOfInput,
-                    pos: Default::default(),
-                })
+        if let Some(token) = it.peek() {
+            records.extend(token.comments.iter().cloned().map(RecordOrCall::Comment));
+        }
+
+        let result = match ctx.peek(it) {
+            (TokenKind::ID(_), _) => parse_record(it, &mut records, ctx),
+            (TokenKind::RecordCall, pos) => {
+                it.next();
+                parse_call(it, '#', pos, ctx).map(|call| records.push(call.into()))
             }
-            Some(token) => match token.kind {
-                lexer::TokenKind::ID(_) => {
-                    let record = parse_record(it)?;
-                    records.push(record);
-                }
-                lexer::TokenKind::RecordCall => {
-                    it.next();
-                    let call = parse_call(it)?;
-                    records.push(call.into());
-                }
 
-                lexer::TokenKind::Separator => (),
-                _ if *end == token.kind => break,
-                lexer::TokenKind::EndOfInput => {
-                    return Err(Error {
-                        error: ErrorTypes::EndOfInput,
-                        pos: Default::default(),
-                    })
-                }
-                _ => todo!("{:?}", token),
-            },
+            (TokenKind::Separator, _) => {
+                it.next();
+                Ok(())
+            }
+            (kind, _) if *kind == end => break,
+            (TokenKind::EndOfInput, pos) => {
+                ctx.report(Error {
+                    error: ErrorTypes::Unclosed {
+                        close: end,
+                        opened: within.opened().unwrap_or_default(),
+                    },
+                    pos,
+                });
+                break;
+            }
+            (found, pos) => Err(unexpected(
+                found,
+                pos,
+                vec![
+                    TokenKind::ID(String::new()),
+                    TokenKind::RecordCall,
+                    TokenKind::Separator,
+                    end.clone(),
+                ],
+                within.clone(),
+            )),
         };
 
-        it.next();
+        if let Err(e) = result {
+            ctx.report(e);
+            synchronize(it, ctx, |kind| {
+                *kind == TokenKind::Separator || *kind == end
+            });
+        }
     }
 
-    Ok(records)
+    records
 }
 
-fn parse_call<'a, T>(it: &mut Peekable<T>) -> Result<Call, Error>
+/// Skips the tokens after a syntax error up to the next one that `stop`
+/// accepts. Brackets opened on the way are skipped as a whole, unmatched
+/// closing ones are dropped.
+fn synchronize<'a, T>(it: &mut Peekable<T>, ctx: &mut Context, stop: impl Fn(&TokenKind) -> bool)
 where
     T: Iterator<Item = &'a lexer::Token>,
 {
-    match it.peek() {
-        None => Err(Error {
-            error: ErrorTypes::EndOfInput,
-            pos: Default::default(),
-        }),
-        Some(token) => match &token.kind {
-            lexer::TokenKind::ID(function) => {
+    let mut depth = 0usize;
+    loop {
+        let (kind, _) = ctx.peek(it);
+        match kind {
+            TokenKind::EndOfInput => {
+                ctx.skipped_to_end = true;
+                return;
+            }
+            _ if depth == 0 && stop(kind) => return,
+            TokenKind::LeftBrace | TokenKind::LeftBracket | TokenKind::LeftParen => depth += 1,
+            TokenKind::RightBrace | TokenKind::RightBracket | TokenKind::RightParen => {
+                depth = depth.saturating_sub(1);
+            }
+            _ => (),
+        }
+        it.next();
+    }
+}
+
+/// Parses the function name and arguments following the `sigil` of a call,
+/// written at `pos`:
+///
+/// ```text
+/// call     = sigil , function , ( "(" , arguments , ")"
+///                            | value
+///                            | named , { named } ) ;
+/// function = identifier , { "." , identifier } ;
+/// named    = identifier , "=" , value ;
+/// ```
+///
+/// Without parentheses a call takes a single positional argument, so a
+/// record following it on the same line is not mistaken for a named one,
+/// or only named arguments, which run up to the end of the line.
+fn parse_call<'a, T>(
+    it: &mut Peekable<T>,
+    sigil: char,
+    pos: FilePos,
+    ctx: &mut Context,
+) -> Result<Call, Error>
+where
+    T: Iterator<Item = &'a lexer::Token>,
+{
+    let mut function = String::new();
+    let mut pos = pos;
+    loop {
+        match ctx.peek(it) {
+            (TokenKind::ID(id), name) => {
+                function.push_str(id);
+                pos.end = name.end;
                 it.next();
-                let value = parse_value(it)?;
-                Ok(Call {
-                    function: function.to_string(),
-                    value: Box::new(value),
-                })
             }
-            _ => Err(Error {
-                error: ErrorTypes::ExpectedIdentifier,
-                pos: token.pos,
+            (found, pos) => {
+                let within = Within::Call(format!("{sigil}{function}"));
+                return Err(unexpected(
+                    found,
+                    pos,
+                    vec![TokenKind::ID(String::new())],
+                    within,
+                ));
+            }
+        }
+        match ctx.peek(it) {
+            (TokenKind::Dot, _) => {
+                function.push('.');
+                it.next();
+            }
+            _ => break,
+        }
+    }
+    let mut args = Vec::new();
+    let mut names = Vec::new();
+    match ctx.peek(it) {
+        (TokenKind::LeftParen, opened) => {
+            it.next();
+            parse_arguments(it, opened, ctx, &mut args, &mut names);
+            it.next();
+        }
+        (TokenKind::ID(_), _) => {
+            while let (TokenKind::ID(name), at) = ctx.peek(it) {
+                parse_named(it, name, at, ctx, &mut args, &mut names)?;
+            }
+        }
+        (_, at) => {
+            let within = Within::Call(format!("{sigil}{function}"));
+            args.push(Argument {
+                value: parse_value(it, ctx, &within)?,
+                pos: at,
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(Call {
+        function,
+        args,
+        pos,
+    })
+}
+
+/// Parses a parenthesized argument list up to and excluding the closing `)`:
+///
+/// ```text
+/// arguments = { break } , [ argument , { delimiter , argument } , delimiter ] ;
+/// argument  = value | named ;
+/// ```
+///
+/// Named arguments come after all positional ones. After an error the rest
+/// of the list is skipped and the error kept in `ctx`.
+fn parse_arguments<'a, T>(
+    it: &mut Peekable<T>,
+    opened: FilePos,
+    ctx: &mut Context,
+    args: &mut Vec<Argument>,
+    names: &mut Vec<FilePos>,
+) where
+    T: Iterator<Item = &'a lexer::Token>,
+{
+    let within = Within::Arguments(opened);
+    let mut leading = Vec::new();
+    let mut comma_allowed = false;
+    loop {
+        let count = args.len();
+        if let Some(token) = it.peek() {
+            let trailing = args.last_mut().map(|a| &mut a.trailing);
+            sort_comments(&token.comments, trailing, &mut leading);
+        }
+
+        let result = match ctx.peek(it) {
+            (TokenKind::RightParen, _) => break,
+            (TokenKind::Separator, _) => {
+                it.next();
+                Ok(())
+            }
+            (TokenKind::Comma, _) if comma_allowed => {
+                comma_allowed = false;
+                it.next();
+                Ok(())
+            }
+            (TokenKind::EndOfInput, pos) => Err(Error {
+                error: ErrorTypes::Unclosed {
+                    close: TokenKind::RightParen,
+                    opened,
+                },
+                pos,
             }),
-        },
+            (TokenKind::ID(name), pos) => {
+                comma_allowed = true;
+                parse_named(it, name, pos, ctx, args, names)
+            }
+            (kind, pos) if starts_value(kind) && names.is_empty() => {
+                comma_allowed = true;
+                parse_value(it, ctx, &within).map(|value| {
+                    args.push(Argument {
+                        value,
+                        pos,
+                        ..Default::default()
+                    });
+                })
+            }
+            (found, pos) => {
+                let mut expected = if names.is_empty() {
+                    value_start().to_vec()
+                } else {
+                    Vec::new()
+                };
+                expected.push(TokenKind::ID(String::new()));
+                if comma_allowed {
+                    expected.push(TokenKind::Comma);
+                }
+                expected.push(TokenKind::RightParen);
+                Err(unexpected(found, pos, expected, within.clone()))
+            }
+        };
+
+        if let Err(e) = result {
+            args.push(Value::Error(e.pos).into());
+            ctx.report(e);
+            synchronize(it, ctx, |kind| *kind == TokenKind::RightParen);
+            break;
+        }
+        if args.len() > count {
+            args[count].leading = mem::take(&mut leading);
+        }
+    }
+
+    if let Some(last) = args.last_mut() {
+        last.trailing.append(&mut leading);
     }
 }
 
+/// Parses `name = value`, with `name` at `pos` being the next token, and
+/// adds it to `args`. `names` holds where the named arguments already in
+/// `args` were written.
+fn parse_named<'a, T>(
+    it: &mut Peekable<T>,
+    name: &str,
+    pos: FilePos,
+    ctx: &mut Context,
+    args: &mut Vec<Argument>,
+    names: &mut Vec<FilePos>,
+) -> Result<(), Error>
+where
+    T: Iterator<Item = &'a lexer::Token>,
+{
+    it.next();
+    match ctx.peek(it) {
+        (TokenKind::Assign, _) => {
+            it.next();
+        }
+        (found, pos) => {
+            let within = Within::Key(name.to_string());
+            return Err(unexpected(found, pos, vec![TokenKind::Assign], within));
+        }
+    }
+    let value = parse_value(it, ctx, &Within::Record(name.to_string()))?;
+
+    let previous = args
+        .iter()
+        .filter_map(|arg| arg.name.as_deref())
+        .position(|n| n == name);
+    if let Some(i) = previous {
+        return Err(Error {
+            error: ErrorTypes::DuplicateArgument {
+                name: name.to_string(),
+                first: names[i],
+            },
+            pos,
+        });
+    }
+    args.push(Argument {
+        name: Some(name.to_string()),
+        value,
+        pos,
+        ..Default::default()
+    });
+    names.push(pos);
+    Ok(())
+}
+
 pub fn parse(tokens: &[lexer::Token]) -> Result<Value, Error> {
+    parse_with_policy(tokens, MergePolicy::default())
+}
+
+/// Like [`parse`], but resolves keys assigned more than once with `policy`.
+pub fn parse_with_policy(tokens: &[lexer::Token], policy: MergePolicy) -> Result<Value, Error> {
+    let (value, errors) = parse_recovering(tokens, policy);
+    match errors.into_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(value),
+    }
+}
+
+/// Parses the whole document, skipping past syntax errors instead of
+/// stopping at the first one. Returns every error found, in source order,
+/// with a partial document where broken values are [`Value::Error`] nodes.
+pub fn parse_recovering(tokens: &[lexer::Token], policy: MergePolicy) -> (Value, Vec<Error>) {
+    let mut ctx = Context {
+        policy,
+        errors: Vec::new(),
+        eof: tokens.last().map_or_else(FilePos::default, |t| FilePos {
+            start: t.pos.end,
+            end: t.pos.end,
+        }),
+        depth: 0,
+        skipped_to_end: false,
+    };
     let mut it = tokens.iter().peekable();
-    let records = parse_multiple_records(&mut it, &lexer::TokenKind::EndOfInput)?;
-    Ok(Value::ObjectWithCalls(records))
+    let records = parse_multiple_records(&mut it, &Within::Document, &mut ctx);
+    (Value::ObjectWithCalls(records), ctx.errors)
 }
 
 #[cfg(test)]
@@ -267,10 +1010,10 @@ mod tests {
 
     #[test]
     fn parsing_number_value() -> Result<(), Error> {
-        let tokens = lexer::tokenize("42");
+        let tokens = lexer::tokenize("42")?;
 
         let mut it = tokens.iter().peekable();
-        let value = parse_value(&mut it)?;
+        let value = parse_value(&mut it, &mut Context::default(), &Within::Document)?;
         assert_eq!(value, Value::Number(42));
 
         Ok(())
@@ -278,32 +1021,55 @@ mod tests {
 
     #[test]
     fn parsing_string_value() -> Result<(), Error> {
-        let tokens = lexer::tokenize("\"some\"");
+        let tokens = lexer::tokenize("\"some\"")?;
         let mut it = tokens.iter().peekable();
-        let value = parse_value(&mut it)?;
+        let value = parse_value(&mut it, &mut Context::default(), &Within::Document)?;
         assert_eq!(value, Value::String("some".to_string()));
         Ok(())
     }
 
     #[test]
     fn parsing_float_value() -> Result<(), Error> {
-        let tokens = lexer::tokenize("4.20");
+        let tokens = lexer::tokenize("4.20")?;
         let mut it = tokens.iter().peekable();
-        let value = parse_value(&mut it)?;
+        let value = parse_value(&mut it, &mut Context::default(), &Within::Document)?;
         assert_eq!(value, Value::Float(4.20));
         Ok(())
     }
 
+    #[test]
+    fn parsing_float_with_leading_zeros() -> Result<(), Error> {
+        let tokens = lexer::tokenize("1.05")?;
+        let mut it = tokens.iter().peekable();
+        assert_eq!(
+            parse_value(&mut it, &mut Context::default(), &Within::Document)?,
+            Value::Float(1.05)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parsing_literals() -> Result<(), Error> {
+        let tokens = lexer::tokenize("[true false null]")?;
+        let mut it = tokens.iter().peekable();
+        assert_eq!(
+            parse_value(&mut it, &mut Context::default(), &Within::Document)?,
+            Value::array(vec![Value::Bool(true), Value::Bool(false), Value::Null])
+        );
+        Ok(())
+    }
+
     #[test]
     fn parsing_value_call() -> Result<(), Error> {
-        let tokens = lexer::tokenize("@call 2");
+        let tokens = lexer::tokenize("@call 2")?;
         let mut it = tokens.iter().peekable();
-        let value = parse_value(&mut it)?;
+        let value = parse_value(&mut it, &mut Context::default(), &Within::Document)?;
         assert_eq!(
             value,
             Value::Call(Call {
                 function: "call".to_string(),
-                value: Box::new(Value::Number(2)),
+                args: vec![Value::Number(2).into()],
+                ..Default::default()
             },)
         );
         Ok(())
@@ -311,18 +1077,20 @@ mod tests {
 
     #[test]
     fn test_parse_record() -> Result<(), Error> {
-        let tokens = lexer::tokenize("x = 2");
+        let tokens = lexer::tokenize("x = 2")?;
 
         let mut it = tokens.iter().peekable();
-        let record = parse_record(&mut it)?;
+        let mut records = Vec::new();
+        parse_record(&mut it, &mut records, &mut Context::default())?;
 
         assert_eq!(
-            record,
-            Record {
+            records,
+            [Record {
                 id: "x".to_string(),
                 value: Value::Number(2),
+                ..Default::default()
             }
-            .into()
+            .into()]
         );
 
         Ok(())
@@ -330,14 +1098,17 @@ mod tests {
 
     #[test]
     fn test_multiple_values() -> Result<(), Error> {
-        let tokens = lexer::tokenize("2 \"asd\"");
+        let tokens = lexer::tokenize("2 \"asd\"]")?;
         println!("{tokens:?}");
         let mut it = tokens.iter().peekable();
-        let array = parse_multiple_values(&mut it)?;
+        let array = parse_multiple_values(&mut it, FilePos::default(), &mut Context::default());
 
         assert_eq!(
             array,
-            vec![Value::Number(2), Value::String("asd".to_string())],
+            vec![
+                Value::Number(2).into(),
+                Value::String("asd".to_string()).into()
+            ],
         );
 
         Ok(())
@@ -345,18 +1116,718 @@ mod tests {
 
     #[test]
     fn parse_array() -> Result<(), Error> {
-        let tokens = lexer::tokenize("x = [1 2];");
+        let tokens = lexer::tokenize("x = [1 2];")?;
         println!("{tokens:?}");
         let mut it = tokens.iter().peekable();
-        let array = parse_record(&mut it)?;
+        let mut records = Vec::new();
+        parse_record(&mut it, &mut records, &mut Context::default())?;
 
-        let RecordOrCall::Record(a) = array else { todo!()};
+        let RecordOrCall::Record(a) = &records[0] else {
+            todo!()
+        };
 
         assert_eq!(
             a.value,
-            Value::Array(vec![Value::Number(1), Value::Number(2),],),
+            Value::array(vec![Value::Number(1), Value::Number(2),],),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_nested_object_without_separators() -> Result<(), Error> {
+        let tokens = lexer::tokenize("x = {a = 1; b = \"c\"} y = 2")?;
+        let parsed = parse(&tokens)?;
+
+        assert_eq!(
+            parsed,
+            Value::ObjectWithCalls(vec![
+                Record {
+                    id: "x".to_string(),
+                    value: Value::ObjectWithCalls(vec![
+                        Record {
+                            id: "a".to_string(),
+                            value: Value::Number(1),
+                            ..Default::default()
+                        }
+                        .into(),
+                        Record {
+                            id: "b".to_string(),
+                            value: Value::String("c".to_string()),
+                            ..Default::default()
+                        }
+                        .into(),
+                    ]),
+                    ..Default::default()
+                }
+                .into(),
+                Record {
+                    id: "y".to_string(),
+                    value: Value::Number(2),
+                    ..Default::default()
+                }
+                .into(),
+            ])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_dotted_keys() -> Result<(), Error> {
+        let tokens = lexer::tokenize(
+            "server.http.port = 8080
+            server.name = \"main\"
+            server.http.host = \"localhost\"",
+        )?;
+        let parsed = parse(&tokens)?;
+        let expected = lexer::tokenize(
+            "server = {
+                http = {port = 8080; host = \"localhost\"}
+                name = \"main\"
+            }",
+        )?;
+
+        assert_eq!(parsed, parse(&expected)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_dotted_keys_into_objects() -> Result<(), Error> {
+        let tokens = lexer::tokenize("a = {x = 1}; a.y = 2; b.c.d = 3")?;
+        let expected = lexer::tokenize("a = {x = 1; y = 2}; b = {c = {d = 3}}")?;
+        assert_eq!(parse(&tokens)?, parse(&expected)?);
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting_dotted_keys() -> Result<(), Error> {
+        let conflict = |s| -> Result<Error, Error> {
+            let tokens = lexer::tokenize(s)?;
+            Ok(parse(&tokens).unwrap_err())
+        };
+
+        let error = conflict("a.b = 1\na.b.c = 2")?;
+        assert!(matches!(error.error, ErrorTypes::ConflictingKey(ref key) if key == "a.b"));
+        assert_eq!(error.pos, FilePos { start: 8, end: 13 });
+
+        let error = conflict("a = 1\na.b = 2")?;
+        assert!(matches!(error.error, ErrorTypes::ConflictingKey(ref key) if key == "a"));
+
+        let error = conflict("a.b = 1\na = 2")?;
+        assert!(matches!(error.error, ErrorTypes::DuplicateKey { ref key, .. } if key == "a"));
+
+        let error = conflict("a.1 = 2")?;
+        assert_eq!(
+            error.error.to_string(),
+            "unexpected number after `a.`, expected identifier"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_keys() -> Result<(), Error> {
+        let tokens = lexer::tokenize("x = 1\nserver.port = 80\nserver = {port = 8080}")?;
+        let error = parse(&tokens).unwrap_err();
+        assert!(matches!(
+            error.error,
+            ErrorTypes::DuplicateKey { ref key, first } if key == "server"
+                && first == FilePos { start: 6, end: 17 }
+        ));
+        assert_eq!(error.pos, FilePos { start: 23, end: 29 });
+
+        let tokens = lexer::tokenize("a = 1; a = 2")?;
+        let error = parse(&tokens).unwrap_err();
+        assert!(matches!(
+            error.error,
+            ErrorTypes::DuplicateKey { ref key, first } if key == "a"
+                && first == FilePos { start: 0, end: 1 }
+        ));
+        assert_eq!(error.pos, FilePos { start: 7, end: 8 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_policies() -> Result<(), Error> {
+        let tokens = lexer::tokenize(
+            "a = {x = 1; y = {z = 2}}
+            b = 1
+            a = {y = {w = 3}}
+            b = {c = 2}",
+        )?;
+        let with = |policy, expected| -> Result<(), Error> {
+            let expected = lexer::tokenize(expected)?;
+            assert_eq!(parse_with_policy(&tokens, policy)?, parse(&expected)?);
+            Ok(())
+        };
+
+        with(MergePolicy::FirstWins, "a = {x = 1; y = {z = 2}}; b = 1")?;
+        with(MergePolicy::LastWins, "a = {y = {w = 3}}; b = {c = 2}")?;
+        with(
+            MergePolicy::DeepMerge,
+            "a = {x = 1; y = {z = 2; w = 3}}; b = {c = 2}",
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn nodes_carry_spans() -> Result<(), Error> {
+        let tokens = lexer::tokenize("#meta-lang 1.0\nserver.port = @call 2")?;
+        let Value::ObjectWithCalls(records) = parse(&tokens)? else {
+            unreachable!()
+        };
+
+        let RecordOrCall::Call(call) = &records[0] else {
+            unreachable!()
+        };
+        assert_eq!(call.pos, FilePos { start: 0, end: 10 });
+
+        let RecordOrCall::Record(server) = &records[1] else {
+            unreachable!()
+        };
+        assert_eq!(server.pos, FilePos { start: 15, end: 26 });
+        let Value::ObjectWithCalls(inner) = &server.value else {
+            unreachable!()
+        };
+        let RecordOrCall::Record(port) = &inner[0] else {
+            unreachable!()
+        };
+        let Value::Call(call) = &port.value else {
+            unreachable!()
+        };
+        assert_eq!(call.pos, FilePos { start: 29, end: 34 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn recover_from_errors() -> Result<(), Error> {
+        let tokens = lexer::tokenize(
+            "a = 1
+            b = = 2
+            c = {x = ]; y = 3}
+            4 = 5
+            d = [1 2]",
+        )?;
+        let (parsed, errors) = parse_recovering(&tokens, MergePolicy::Error);
+
+        let errors = errors
+            .iter()
+            .map(|e| (e.error.to_string(), e.pos.start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                ("unexpected `=` after `b =`, expected value".to_string(), 22),
+                ("missing value after `x =`".to_string(), 47),
+                (
+                    "unexpected number at the top level, expected identifier, `#`, \
+                     separator or end of input"
+                        .to_string(),
+                    69
+                ),
+            ]
+        );
+
+        let expected = Value::ObjectWithCalls(vec![
+            Record {
+                id: "a".to_string(),
+                value: Value::Number(1),
+                ..Default::default()
+            }
+            .into(),
+            Record {
+                id: "b".to_string(),
+                value: Value::Error(FilePos { start: 22, end: 23 }),
+                ..Default::default()
+            }
+            .into(),
+            Record {
+                id: "c".to_string(),
+                value: Value::ObjectWithCalls(vec![
+                    Record {
+                        id: "x".to_string(),
+                        value: Value::Error(FilePos { start: 47, end: 48 }),
+                        ..Default::default()
+                    }
+                    .into(),
+                    Record {
+                        id: "y".to_string(),
+                        value: Value::Number(3),
+                        ..Default::default()
+                    }
+                    .into(),
+                ]),
+                ..Default::default()
+            }
+            .into(),
+            Record {
+                id: "d".to_string(),
+                value: Value::array(vec![Value::Number(1), Value::Number(2)]),
+                ..Default::default()
+            }
+            .into(),
+        ]);
+        assert_eq!(parsed, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn recover_inside_arrays_and_arguments() -> Result<(), Error> {
+        let tokens = lexer::tokenize("a = [\n 1 = 2\n 3\n]\nb = @f(1 2 = 3, [4])\nc = 1")?;
+        let (parsed, errors) = parse_recovering(&tokens, MergePolicy::Error);
+
+        let errors = errors
+            .iter()
+            .map(|e| (e.error.to_string(), e.pos.start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                (
+                    "unexpected `=` in array, expected value, `,` or `]`".to_string(),
+                    9
+                ),
+                (
+                    "unexpected `=` in arguments, expected value, identifier, `,` or `)`"
+                        .to_string(),
+                    29
+                ),
+            ]
+        );
+
+        let expected = lexer::tokenize("a = [1 0]\nb = @f(1 2)\nc = 1")?;
+        let Value::ObjectWithCalls(mut expected) = parse(&expected)? else {
+            unreachable!()
+        };
+        let error = |start| {
+            Value::Error(FilePos {
+                start,
+                end: start + 1,
+            })
+        };
+        if let RecordOrCall::Record(a) = &mut expected[0] {
+            a.value = Value::array(vec![Value::Number(1), error(9)]);
+        }
+        if let RecordOrCall::Record(Record {
+            value: Value::Call(b),
+            ..
+        }) = &mut expected[1]
+        {
+            b.args.push(error(29).into());
+        }
+        assert_eq!(parsed, Value::ObjectWithCalls(expected));
+
+        // a delimiter left open by an error is not reported again
+        let tokens = lexer::tokenize("a = {b = [1 = 2\nc = (")?;
+        let (_, errors) = parse_recovering(&tokens, MergePolicy::Error);
+        assert_eq!(errors.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn recovery_never_panics() -> Result<(), Error> {
+        let broken = [
+            "}",
+            "]]",
+            "x = {",
+            "@",
+            "#",
+            "x.",
+            "= = =",
+            "x = @",
+            "{ } ] [",
+            "x = {a = [}",
+            "x = {a = 1 ] b = 2}",
+            "#f ] y = 1",
+            "x = [{]",
+            "x y z",
+            "x = {{{",
+        ];
+        for source in broken {
+            let tokens = lexer::tokenize(source)?;
+            let (_, errors) = parse_recovering(&tokens, MergePolicy::Error);
+            assert!(!errors.is_empty(), "no error for {source:?}");
+            assert!(parse(&tokens).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn nesting_limit() -> Result<(), Error> {
+        let error = |source: &str| -> Result<Vec<String>, Error> {
+            let tokens = lexer::tokenize(source)?;
+            let (_, errors) = parse_recovering(&tokens, MergePolicy::Error);
+            Ok(errors.iter().map(|e| e.error.to_string()).collect())
+        };
+        let too_deep = vec![format!("nested more than {MAX_DEPTH} levels deep")];
+
+        assert_eq!(error(&format!("x = {}", "[".repeat(10_000)))?, too_deep);
+        assert_eq!(error(&format!("x = {}", "{a = ".repeat(10_000)))?, too_deep);
+        assert_eq!(error(&format!("x = {}1", "@f ".repeat(10_000)))?, too_deep);
+        assert_eq!(
+            error(&format!("{} = 1", ["a"; 10_000].join(".")))?,
+            too_deep
+        );
+
+        let nested = |n| format!("x = {}{}", "[".repeat(n), "]".repeat(n));
+        assert!(error(&nested(MAX_DEPTH))?.is_empty());
+        assert_eq!(error(&nested(MAX_DEPTH + 1))?, too_deep);
+        Ok(())
+    }
+
+    #[test]
+    fn error_messages() -> Result<(), Error> {
+        let error = |s| -> Result<Error, Error> {
+            let tokens = lexer::tokenize(s)?;
+            Ok(parse(&tokens).unwrap_err())
+        };
+
+        let e = error("x = 1\ny = [1 2")?;
+        assert_eq!(e.error.to_string(), "expected `]` to close array");
+        assert!(matches!(
+            e.error,
+            ErrorTypes::Unclosed {
+                opened: FilePos { start: 10, .. },
+                ..
+            }
+        ));
+        assert_eq!(e.pos, FilePos { start: 14, end: 14 });
+
+        let e = error("x = {a = 1")?;
+        assert!(matches!(
+            e.error,
+            ErrorTypes::Unclosed { close: TokenKind::RightBrace, opened } if opened.start == 4
+        ));
+        assert_eq!(e.pos, FilePos { start: 10, end: 10 });
+
+        let e = error("x = [1 = 2]")?;
+        assert_eq!(
+            e.error.to_string(),
+            "unexpected `=` in array, expected value, `,` or `]`"
+        );
+        assert!(matches!(
+            e.error,
+            ErrorTypes::Unexpected { within: Within::Array(opened), .. } if opened.start == 4
+        ));
+
+        let e = error("x = @call\ny = 2")?;
+        assert_eq!(e.error.to_string(), "missing value after `@call`");
+        assert_eq!(e.pos, FilePos { start: 9, end: 10 });
+
+        let e = error("x = @")?;
+        assert_eq!(
+            e.error.to_string(),
+            "unexpected end of input after `@`, expected identifier"
+        );
+        assert_eq!(e.pos, FilePos { start: 5, end: 5 });
+
+        let e = error("x = {a = 1 ]}")?;
+        assert_eq!(
+            e.error.to_string(),
+            "unexpected `]` in object, expected identifier, `#`, separator or `}`"
+        );
+
+        let e = error("x")?;
+        assert_eq!(
+            e.error.to_string(),
+            "unexpected end of input after `x`, expected `=` or `.`"
+        );
+        assert_eq!(e.pos, FilePos { start: 1, end: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_without_end_of_input_token() {
+        let tokens = lexer::tokenize("x = 1").unwrap();
+        let e = parse(&tokens[..2]).unwrap_err();
+        assert_eq!(e.error.to_string(), "missing value after `x =`");
+        assert_eq!(e.pos, FilePos { start: 3, end: 3 });
+    }
+
+    fn array(source: &str) -> Result<Value, Error> {
+        let tokens = lexer::tokenize(&format!("x = {source}"))?;
+        let Value::ObjectWithCalls(mut records) = parse(&tokens)? else {
+            unreachable!()
+        };
+        match records.pop() {
+            Some(RecordOrCall::Record(record)) => Ok(record.value),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn array_delimiters() -> Result<(), Error> {
+        let numbers = |n: &[i64]| Value::array(n.iter().copied().map(Value::Number));
+
+        let cases: &[(&str, &[i64])] = &[
+            ("[]", &[]),
+            ("[ ]", &[]),
+            ("[\n]", &[]),
+            ("[\n\n]", &[]),
+            ("[1]", &[1]),
+            ("[1,]", &[1]),
+            ("[1 2 3]", &[1, 2, 3]),
+            ("[1, 2, 3]", &[1, 2, 3]),
+            ("[1,2,3]", &[1, 2, 3]),
+            ("[1 , 2 ,3]", &[1, 2, 3]),
+            ("[1, 2, 3,]", &[1, 2, 3]),
+            ("[1, 2 3]", &[1, 2, 3]),
+            ("[1\n2\n3]", &[1, 2, 3]),
+            ("[1;2;3]", &[1, 2, 3]),
+            ("[\n  1\n  2\n]", &[1, 2]),
+            ("[\n  1,\n  2,\n]", &[1, 2]),
+            ("[\n  1\n  , 2\n]", &[1, 2]),
+            ("[1\n\n\n2]", &[1, 2]),
+            ("[1, // one\n 2 /* two */]", &[1, 2]),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(array(source)?, numbers(expected), "{source:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn nested_arrays() -> Result<(), Error> {
+        let object = |id: &str, n| {
+            Value::ObjectWithCalls(vec![Record {
+                id: id.to_string(),
+                value: Value::Number(n),
+                ..Default::default()
+            }
+            .into()])
+        };
+        let call = |value: Value| {
+            Value::Call(Call {
+                function: "f".to_string(),
+                args: vec![value.into()],
+                ..Default::default()
+            })
+        };
+        let nested = Value::array(vec![
+            Value::array(vec![Value::Number(1), Value::Number(2)]),
+            Value::array(vec![]),
+            Value::array(vec![Value::array(vec![Value::Number(3)])]),
+        ]);
+
+        for source in [
+            "[[1 2] [] [[3]]]",
+            "[[1, 2], [], [[3,],],]",
+            "[\n[1\n2]\n[]\n[[3]]\n]",
+        ] {
+            assert_eq!(array(source)?, nested, "{source:?}");
+        }
+
+        let objects = Value::array(vec![object("a", 1), object("b", 2)]);
+        for source in [
+            "[{a = 1} {b = 2}]",
+            "[{a = 1}, {b = 2},]",
+            "[\n  {\n    a = 1\n  }\n  {b = 2;}\n]",
+        ] {
+            assert_eq!(array(source)?, objects, "{source:?}");
+        }
+
+        let calls = Value::array(vec![
+            call(Value::Number(1)),
+            call(Value::array(vec![Value::Number(2), Value::Number(3)])),
+            Value::Number(4),
+        ]);
+        for source in [
+            "[@f 1 @f [2 3] 4]",
+            "[@f 1, @f [2, 3], 4]",
+            "[\n@f 1\n@f [2\n3]\n4\n]",
+        ] {
+            assert_eq!(array(source)?, calls, "{source:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn array_errors() -> Result<(), Error> {
+        let error =
+            |source| -> Result<String, Error> { Ok(array(source).unwrap_err().error.to_string()) };
+
+        assert_eq!(
+            error("[,]")?,
+            "unexpected `,` in array, expected value or `]`"
+        );
+        assert_eq!(
+            error("[, 1]")?,
+            "unexpected `,` in array, expected value or `]`"
+        );
+        assert_eq!(
+            error("[1,, 2]")?,
+            "unexpected `,` in array, expected value or `]`"
+        );
+        assert_eq!(
+            error("[1,\n, 2]")?,
+            "unexpected `,` in array, expected value or `]`"
+        );
+        assert_eq!(
+            error("[1 = 2]")?,
+            "unexpected `=` in array, expected value, `,` or `]`"
+        );
+        assert_eq!(
+            error("[1 }")?,
+            "unexpected `}` in array, expected value, `,` or `]`"
+        );
+        assert_eq!(error("[1 2")?, "expected `]` to close array");
+        assert_eq!(error("[1,\n")?, "expected `]` to close array");
+        assert_eq!(error("[[1]")?, "expected `]` to close array");
+        assert_eq!(error("[@f]")?, "missing value after `@f`");
+        assert_eq!(error("[@f, 1]")?, "missing value after `@f`");
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_arguments() -> Result<(), Error> {
+        let named = |name: &str, value| Argument {
+            name: Some(name.to_string()),
+            value,
+            ..Default::default()
+        };
+        let call = |args| {
+            Value::Call(Call {
+                function: "f".to_string(),
+                args,
+                ..Default::default()
+            })
+        };
+        let string = |s: &str| Value::String(s.to_string());
+
+        assert_eq!(array("@f()")?, call(vec![]));
+        assert_eq!(array("@f(1)")?, call(vec![Value::Number(1).into()]));
+        assert_eq!(
+            array("@f(\", \", [1], sep = \"-\",)")?,
+            call(vec![
+                string(", ").into(),
+                Value::array(vec![Value::Number(1)]).into(),
+                named("sep", string("-")),
+            ])
+        );
+        assert_eq!(
+            array("@f(\n    1\n    a = 2\n)")?,
+            call(vec![Value::Number(1).into(), named("a", Value::Number(2))])
+        );
+        assert_eq!(
+            array("@f(1, a = @g 2)")?,
+            call(vec![
+                Value::Number(1).into(),
+                named(
+                    "a",
+                    Value::Call(Call {
+                        function: "g".to_string(),
+                        args: vec![Value::Number(2).into()],
+                        ..Default::default()
+                    })
+                ),
+            ])
+        );
+
+        assert!(matches!(
+            array("@std.url \"x\"")?,
+            Value::Call(Call { function, .. }) if function == "std.url"
+        ));
+
+        // without parentheses a record after the argument is not one
+        let tokens = lexer::tokenize("x = @f \"a\" y = 3\n#g 1 z = 4")?;
+        let expected = lexer::tokenize("x = @f(\"a\")\ny = 3\n#g(1)\nz = 4")?;
+        assert_eq!(parse(&tokens)?, parse(&expected)?);
+
+        // but named arguments alone run up to the end of the line
+        assert_eq!(
+            array("@env name = \"HOME\" default = \"/\"")?,
+            Value::Call(Call {
+                function: "env".to_string(),
+                args: vec![named("name", string("HOME")), named("default", string("/"))],
+                ..Default::default()
+            })
         );
+        let tokens = lexer::tokenize("#f a = 1 b = 2\nc = 3")?;
+        let expected = lexer::tokenize("#f(a = 1, b = 2)\nc = 3")?;
+        assert_eq!(parse(&tokens)?, parse(&expected)?);
+        let tokens = lexer::tokenize("#f a = 1 a = 2")?;
+        assert!(matches!(
+            parse(&tokens).unwrap_err().error,
+            ErrorTypes::DuplicateArgument { .. }
+        ));
+
+        Ok(())
+    }
 
+    #[test]
+    fn call_argument_errors() {
+        let error = |source| array(source).unwrap_err();
+
+        assert_eq!(
+            error("@f(a = 1, 2)").error.to_string(),
+            "unexpected number in arguments, expected identifier or `)`"
+        );
+        assert_eq!(
+            error("@f(1 2,, 3)").error.to_string(),
+            "unexpected `,` in arguments, expected value, identifier or `)`"
+        );
+        assert_eq!(
+            error("@f(a 1)").error.to_string(),
+            "unexpected number after `a`, expected `=`"
+        );
+        assert_eq!(
+            error("@f(1").error.to_string(),
+            "expected `)` to close argument list"
+        );
+        assert_eq!(
+            error("@f(a =)").error.to_string(),
+            "missing value after `a =`"
+        );
+        assert_eq!(
+            error("@std.(1)").error.to_string(),
+            "unexpected `(` after `@std.`, expected identifier"
+        );
+
+        let e = error("@f(a = 1, a = 2)");
+        assert_eq!(e.error.to_string(), "argument a is passed twice");
+        assert!(matches!(
+            e.error,
+            ErrorTypes::DuplicateArgument {
+                first: FilePos { start: 7, .. },
+                ..
+            }
+        ));
+        assert_eq!(e.pos, FilePos { start: 14, end: 15 });
+    }
+
+    #[test]
+    fn references() -> Result<(), Error> {
+        assert_eq!(
+            array("[@ref a.b, @refs()]")?,
+            Value::array(vec![
+                Value::Reference(Reference {
+                    path: vec!["a".to_string(), "b".to_string()],
+                    pos: FilePos { start: 5, end: 13 },
+                }),
+                Value::Call(Call {
+                    function: "refs".to_string(),
+                    ..Default::default()
+                }),
+            ])
+        );
+        assert_eq!(
+            array("@ref(a)").unwrap_err().error.to_string(),
+            "unexpected `(` after `@ref`, expected identifier"
+        );
+        assert_eq!(
+            array("@ref a.").unwrap_err().error.to_string(),
+            "unexpected end of input after `a.`, expected identifier"
+        );
         Ok(())
     }
 }
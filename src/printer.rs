@@ -0,0 +1,599 @@
+use crate::ast::{Argument, Call, Record, RecordOrCall, Value};
+use crate::lexer::{self, Comment, FilePos, Token};
+use core::fmt;
+use std::error::Error as StdError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    ExpectedObject,
+    InvalidKey(String),
+    UnrepresentableFloat(f64),
+    UnsupportedComment(FilePos),
+    Unparsed(FilePos),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExpectedObject => write!(f, "expected an object at the top level"),
+            Self::InvalidKey(key) => write!(f, "\"{key}\" is not a valid identifier"),
+            Self::UnrepresentableFloat(x) => write!(f, "float {x} cannot be represented in alt"),
+            Self::UnsupportedComment(pos) => {
+                write!(
+                    f,
+                    "comment at {pos} is not between records and would be lost"
+                )
+            }
+            Self::Unparsed(pos) => write!(f, "value at {pos} failed to parse"),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+const INDENT: &str = "    ";
+
+/// Prints a whole document in canonical form: one record or record call per
+/// line, nested objects indented by four spaces and arrays kept on one line.
+pub fn print(root: &Value) -> Result<String, Error> {
+    let mut out = String::new();
+    match root {
+        Value::ObjectWithCalls(records) => print_entries(&mut out, records, 0)?,
+        Value::Object(records) => {
+            for record in records {
+                print_record(&mut out, &[&record.id], record, 0)?;
+            }
+        }
+        _ => return Err(Error::ExpectedObject),
+    }
+    Ok(out)
+}
+
+/// Prints a single value the way it would appear on the right side of a
+/// record at the top level.
+pub fn print_value(value: &Value) -> Result<String, Error> {
+    let mut out = String::new();
+    write_value(&mut out, value, 0)?;
+    Ok(out)
+}
+
+/// A line of an object body.
+enum Line<'a> {
+    /// A record under the ids of its key.
+    Record(Vec<&'a str>, &'a Record),
+    Call(&'a Call),
+    Comment(&'a Comment),
+}
+
+/// Collects the lines of an object body along with where they were written.
+/// Records with a dotted key are stored nested under their prefix, so they
+/// are looked for below the body and printed back at the level they were
+/// written at.
+fn gather<'a>(
+    entries: &'a [RecordOrCall],
+    prefix: &[&'a str],
+    lines: &mut Vec<(FilePos, Line<'a>)>,
+) {
+    for entry in entries {
+        match entry {
+            RecordOrCall::Record(record) => {
+                let key = [prefix, &[record.id.as_str()]].concat();
+                if !record.implicit && record.key.len().saturating_sub(1) == prefix.len() {
+                    lines.push((record.pos, Line::Record(key.clone(), record)));
+                }
+                if let Value::ObjectWithCalls(inner) = &record.value {
+                    gather(inner, &key, lines);
+                }
+            }
+            RecordOrCall::Call(call) if prefix.is_empty() => {
+                lines.push((call.pos, Line::Call(call)));
+            }
+            RecordOrCall::Comment(comment) if prefix.is_empty() => {
+                lines.push((comment.pos, Line::Comment(comment)));
+            }
+            RecordOrCall::Call(_) | RecordOrCall::Comment(_) => (),
+        }
+    }
+}
+
+/// Prints the records, calls and comments of an object body in the order
+/// they were written. Entries built in code stay after the entry before them.
+fn print_entries(out: &mut String, entries: &[RecordOrCall], depth: usize) -> Result<(), Error> {
+    let mut lines = Vec::new();
+    gather(entries, &[], &mut lines);
+    let mut start = 0;
+    let mut lines: Vec<_> = lines
+        .into_iter()
+        .map(|(pos, line)| {
+            if pos != FilePos::default() {
+                start = pos.start;
+            }
+            (start, line)
+        })
+        .collect();
+    lines.sort_by_key(|(start, _)| *start);
+
+    for (_, line) in lines {
+        match line {
+            Line::Record(key, record) => print_record(out, &key, record, depth)?,
+            Line::Call(call) => {
+                out.push_str(&INDENT.repeat(depth));
+                write_call(out, '#', call, depth)?;
+                out.push('\n');
+            }
+            Line::Comment(comment) => print_comment(out, comment, depth),
+        }
+    }
+    Ok(())
+}
+
+fn print_comment(out: &mut String, comment: &Comment, depth: usize) {
+    // a comment trailing a record stays on the record's line
+    if !comment.own_line && out.ends_with('\n') {
+        out.pop();
+        out.push(' ');
+    } else {
+        out.push_str(&INDENT.repeat(depth));
+    }
+    out.push_str(&comment.to_string());
+    out.push('\n');
+}
+
+/// Checks that every comment in `tokens` made it into `root`, so printing
+/// `root` does not lose any of them. The parser only keeps comments that sit
+/// between records, array elements or arguments.
+pub fn check_comments(tokens: &[Token], root: &Value) -> Result<(), Error> {
+    fn collect_call(call: &Call, kept: &mut Vec<FilePos>) {
+        for arg in &call.args {
+            kept.extend(arg.leading.iter().chain(&arg.trailing).map(|c| c.pos));
+            collect(&arg.value, kept);
+        }
+    }
+
+    fn collect(value: &Value, kept: &mut Vec<FilePos>) {
+        match value {
+            Value::ObjectWithCalls(records) => {
+                for record in records {
+                    match record {
+                        RecordOrCall::Record(r) => collect(&r.value, kept),
+                        RecordOrCall::Call(c) => collect_call(c, kept),
+                        RecordOrCall::Comment(c) => kept.push(c.pos),
+                    }
+                }
+            }
+            Value::Object(records) => records.iter().for_each(|r| collect(&r.value, kept)),
+            Value::Array(values) => {
+                for element in values {
+                    kept.extend(
+                        element
+                            .leading
+                            .iter()
+                            .chain(&element.trailing)
+                            .map(|c| c.pos),
+                    );
+                    collect(&element.value, kept);
+                }
+            }
+            Value::Call(c) => collect_call(c, kept),
+            Value::Typed(t) => collect(&t.value, kept),
+            Value::Null
+            | Value::Bool(_)
+            | Value::Number(_)
+            | Value::Float(_)
+            | Value::String(_)
+            | Value::Reference(_)
+            | Value::Error(_) => (),
+        }
+    }
+
+    let mut kept = Vec::new();
+    collect(root, &mut kept);
+    tokens
+        .iter()
+        .flat_map(|t| t.comments.iter())
+        .find(|c| !kept.contains(&c.pos))
+        .map_or(Ok(()), |c| Err(Error::UnsupportedComment(c.pos)))
+}
+
+fn print_record(
+    out: &mut String,
+    key: &[&str],
+    record: &Record,
+    depth: usize,
+) -> Result<(), Error> {
+    if let Some(id) = key.iter().find(|id| !lexer::is_identifier(id)) {
+        return Err(Error::InvalidKey((*id).to_string()));
+    }
+    out.push_str(&INDENT.repeat(depth));
+    out.push_str(&key.join("."));
+    out.push_str(" = ");
+    write_value(out, &record.value, depth)?;
+    out.push('\n');
+    Ok(())
+}
+
+/// Prints `@f value` for a call with one positional argument and
+/// `@f(a, name = b)` for any other. Arguments with comments go on lines of
+/// their own.
+fn write_call(out: &mut String, sigil: char, call: &Call, depth: usize) -> Result<(), Error> {
+    write_function(out, sigil, &call.function)?;
+    let commented = call
+        .args
+        .iter()
+        .any(|a| !a.leading.is_empty() || !a.trailing.is_empty());
+    if let Some(value) = call.single().filter(|_| !commented) {
+        out.push(' ');
+        return write_value(out, value, depth);
+    }
+
+    if commented {
+        out.push_str("(\n");
+        for (i, arg) in call.args.iter().enumerate() {
+            let separator = if i + 1 < call.args.len() { "," } else { "" };
+            write_commented(
+                out,
+                &arg.leading,
+                &arg.trailing,
+                separator,
+                depth + 1,
+                |out| write_argument(out, arg, depth + 1),
+            )?;
+        }
+        out.push_str(&INDENT.repeat(depth));
+        out.push(')');
+        return Ok(());
+    }
+
+    out.push('(');
+    for (i, arg) in call.args.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_argument(out, arg, depth)?;
+    }
+    out.push(')');
+    Ok(())
+}
+
+fn write_argument(out: &mut String, arg: &Argument, depth: usize) -> Result<(), Error> {
+    if let Some(name) = &arg.name {
+        if !lexer::is_identifier(name) {
+            return Err(Error::InvalidKey(name.clone()));
+        }
+        out.push_str(name);
+        out.push_str(" = ");
+    }
+    write_value(out, &arg.value, depth)
+}
+
+/// Writes an array element or argument on a line of its own, its leading
+/// comments on the lines before. `separator` goes between the item and the
+/// comments trailing it.
+fn write_commented(
+    out: &mut String,
+    leading: &[Comment],
+    trailing: &[Comment],
+    separator: &str,
+    depth: usize,
+    write: impl FnOnce(&mut String) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let indent = INDENT.repeat(depth);
+    for comment in leading {
+        out.push_str(&indent);
+        out.push_str(&comment.to_string());
+        out.push('\n');
+    }
+    out.push_str(&indent);
+    write(out)?;
+    out.push_str(separator);
+    for comment in trailing {
+        if comment.own_line {
+            out.push('\n');
+            out.push_str(&indent);
+        } else {
+            out.push(' ');
+        }
+        out.push_str(&comment.to_string());
+    }
+    out.push('\n');
+    Ok(())
+}
+
+fn write_function(out: &mut String, sigil: char, function: &str) -> Result<(), Error> {
+    if !function.split('.').all(lexer::is_identifier) {
+        return Err(Error::InvalidKey(function.to_string()));
+    }
+    out.push(sigil);
+    out.push_str(function);
+    Ok(())
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            _ if ch.is_control() => out.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn write_value(out: &mut String, value: &Value, depth: usize) -> Result<(), Error> {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(&b.to_string()),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::Float(f) => {
+            if !f.is_finite() {
+                return Err(Error::UnrepresentableFloat(*f));
+            }
+            // debug formatting always keeps a `.` or an exponent, so the
+            // number is read back as a float
+            out.push_str(&format!("{f:?}"));
+        }
+        Value::String(s) => write_string(out, s),
+        Value::Array(values)
+            if values
+                .iter()
+                .all(|e| e.leading.is_empty() && e.trailing.is_empty()) =>
+        {
+            out.push('[');
+            for (i, element) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                write_value(out, &element.value, depth)?;
+            }
+            out.push(']');
+        }
+        // elements with comments go on lines of their own
+        Value::Array(values) => {
+            out.push_str("[\n");
+            for element in values {
+                write_commented(
+                    out,
+                    &element.leading,
+                    &element.trailing,
+                    "",
+                    depth + 1,
+                    |out| write_value(out, &element.value, depth + 1),
+                )?;
+            }
+            out.push_str(&INDENT.repeat(depth));
+            out.push(']');
+        }
+        Value::ObjectWithCalls(records) if records.is_empty() => out.push_str("{}"),
+        Value::Object(records) if records.is_empty() => out.push_str("{}"),
+        Value::ObjectWithCalls(records) => {
+            out.push_str("{\n");
+            print_entries(out, records, depth + 1)?;
+            out.push_str(&INDENT.repeat(depth));
+            out.push('}');
+        }
+        Value::Object(records) => {
+            out.push_str("{\n");
+            for record in records {
+                print_record(out, &[&record.id], record, depth + 1)?;
+            }
+            out.push_str(&INDENT.repeat(depth));
+            out.push('}');
+        }
+        Value::Call(call) => write_call(out, '@', call, depth)?,
+        Value::Reference(r) => out.push_str(&r.to_string()),
+        Value::Error(pos) => return Err(Error::Unparsed(*pos)),
+        // typed values come from calls, so printing the call back keeps the
+        // kind once the document is evaluated again
+        Value::Typed(t) => {
+            write_function(out, '@', &t.kind)?;
+            out.push(' ');
+            write_value(out, &t.value, depth)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ast::Typed,
+        lexer::tokenize,
+        merge::MergePolicy,
+        parser::{parse, parse_with_policy},
+    };
+
+    fn round_trip(source: &str) -> Result<String, Box<dyn StdError>> {
+        let parsed = parse(&tokenize(source)?)?;
+        let printed = print(&parsed)?;
+        assert_eq!(parse(&tokenize(&printed)?)?, parsed);
+        Ok(printed)
+    }
+
+    #[test]
+    fn print_canonical() -> Result<(), Box<dyn StdError>> {
+        let printed = round_trip(
+            "#meta-lang 1.0; x=2;y   =\"str\"\nz = {a = [1 2 {}] ; b = {c = @call 2}}\n#pisoi \"tom\"",
+        )?;
+
+        assert_eq!(
+            printed,
+            "#meta-lang 1.0
+x = 2
+y = \"str\"
+z = {
+    a = [1 2 {}]
+    b = {
+        c = @call 2
+    }
+}
+#pisoi \"tom\"
+"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn print_is_idempotent() -> Result<(), Box<dyn StdError>> {
+        let printed = round_trip("a = [{x = 1} \"b\" 4.5 true null] b = {}")?;
+        assert_eq!(round_trip(&printed)?, printed);
+        Ok(())
+    }
+
+    #[test]
+    fn print_calls() -> Result<(), Box<dyn StdError>> {
+        let printed = round_trip("#env(name = \"HOME\")\nx = @f(\n1, [2],\nsep=\"-\")\ny = @g()")?;
+        assert_eq!(
+            printed,
+            "#env(name = \"HOME\")\nx = @f(1, [2], sep = \"-\")\ny = @g()\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn print_numbers() -> Result<(), Box<dyn StdError>> {
+        let printed = round_trip("x = [-3 0xFF 1_000 1.05 -2.5e-9 1e300 7.0]")?;
+        assert_eq!(printed, "x = [-3 255 1000 1.05 -2.5e-9 1e300 7.0]\n");
+        Ok(())
+    }
+
+    #[test]
+    fn print_typed() -> Result<(), Error> {
+        let value = Value::Typed(Typed {
+            kind: "std_url".to_string(),
+            value: Box::new(Value::String("localhost".to_string())),
+        });
+        assert_eq!(print_value(&value)?, "@std_url \"localhost\"");
+        Ok(())
+    }
+
+    #[test]
+    fn print_requires_object() {
+        assert_eq!(print(&Value::Number(2)), Err(Error::ExpectedObject));
+    }
+
+    #[test]
+    fn print_invalid_key() {
+        let value = Value::Object(vec![Record {
+            id: "two words".to_string(),
+            value: Value::Number(2),
+            ..Default::default()
+        }]);
+        assert_eq!(
+            print(&value),
+            Err(Error::InvalidKey("two words".to_string()))
+        );
+    }
+
+    #[test]
+    fn print_comments() -> Result<(), Box<dyn StdError>> {
+        let source = "// about x
+x = 2 // why two
+y = { // nested
+    /* first */ a = 1
+    b = 2;  // trailing
+}
+// the end
+";
+        let parsed = parse(&tokenize(source)?)?;
+        let printed = print(&parsed)?;
+
+        assert_eq!(
+            printed,
+            "// about x
+x = 2 // why two
+y = { // nested
+    /* first */
+    a = 1
+    b = 2 // trailing
+}
+// the end
+"
+        );
+        assert_eq!(print(&parse(&tokenize(&printed)?)?)?, printed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn comments_inside_values_are_reported() -> Result<(), Box<dyn StdError>> {
+        let tokens = tokenize("x = [/* lost */]\n// kept\n")?;
+        let parsed = parse(&tokens)?;
+        assert_eq!(
+            check_comments(&tokens, &parsed),
+            Err(Error::UnsupportedComment(FilePos { start: 5, end: 15 }))
+        );
+
+        let tokens = tokenize("x = [1 /* kept */ 2] // kept\n")?;
+        let parsed = parse(&tokens)?;
+        assert_eq!(check_comments(&tokens, &parsed), Ok(()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn print_dotted_keys() -> Result<(), Box<dyn StdError>> {
+        for source in [
+            "a.b = 1\nx = 2\na.c.d = 3\n",
+            "a = {\n    x = 1\n    y.z = 2\n}\na.w = 3\n",
+            "#f 1\nserver.port = 80 // http\nname = \"x\"\nserver.host = \"localhost\"\n",
+        ] {
+            assert_eq!(round_trip(source)?, source);
+        }
+
+        let tokens = tokenize("a.b = 1\nx = 2\na = {\n    c = 3\n}\n")?;
+        let parsed = parse_with_policy(&tokens, MergePolicy::DeepMerge)?;
+        assert_eq!(print(&parsed)?, "a.b = 1\nx = 2\na = {\n    c = 3\n}\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn print_comments_in_values() -> Result<(), Box<dyn StdError>> {
+        let source = "ports = [
+    80 // http
+    443 // https
+]
+x = @f(
+    // the first
+    1, /* one */
+    name = [2 3] // the last
+    // after all
+)
+";
+        let tokens = tokenize(source)?;
+        let parsed = parse(&tokens)?;
+        check_comments(&tokens, &parsed)?;
+        assert_eq!(print(&parsed)?, source);
+
+        let tokens = tokenize("x = [1 /* one */ // also one\n\n// two\n2]")?;
+        let parsed = parse(&tokens)?;
+        check_comments(&tokens, &parsed)?;
+        assert_eq!(
+            print(&parsed)?,
+            "x = [\n    1 /* one */ // also one\n    // two\n    2\n]\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn print_escaped_strings() -> Result<(), Box<dyn StdError>> {
+        let printed = round_trip("x = \"\"\"\n    \"quoted\"\n    \\\\path\u{7}\n    \"\"\"")?;
+        assert_eq!(printed, "x = \"\\\"quoted\\\"\\n\\\\path\\u{7}\\n\"\n");
+        Ok(())
+    }
+    #[test]
+    fn print_references() -> Result<(), Box<dyn StdError>> {
+        let printed = round_trip("a = [@ref b.c  @upper @ref d]")?;
+        assert_eq!(printed, "a = [@ref b.c @upper @ref d]\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+use crate::ast::{Call, Record, Value};
+use crate::convert::{FromValue, IntoValue};
+use crate::eval::{self, Error};
+use crate::lexer::FilePos;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+// Functions are shared rather than owned so that one can be called again
+// while it runs, as when an included document includes another.
+type BoxedValueFunction = Rc<dyn Fn(&mut dyn eval::Evaluator, &Call) -> Result<Value, Error>>;
+type BoxedRecordFunction =
+    Rc<dyn Fn(&mut dyn eval::Evaluator, &Call) -> Result<Vec<Record>, Error>>;
+
+/// A function that can be registered as `@name`. Implemented for closures
+/// taking up to four positional arguments read with [`FromValue`], where
+/// trailing [`Option`]s may be left out.
+pub trait ValueFunction<Args> {
+    fn call(&mut self, call: &Call) -> Result<Value, Error>;
+}
+
+/// A function that can be registered as `#name`, like [`ValueFunction`].
+pub trait RecordFunction<Args> {
+    fn call(&mut self, call: &Call) -> Result<Option<Record>, Error>;
+}
+
+/// How many of the arguments, which are `optional` or not, must be passed:
+/// all up to the last one that is not optional.
+fn required(optional: &[bool]) -> usize {
+    optional
+        .iter()
+        .rposition(|optional| !optional)
+        .map_or(0, |i| i + 1)
+}
+
+/// The positional arguments of `call`, `required` to `max` of them and no
+/// named ones.
+fn positional(call: &Call, required: usize, max: usize) -> Result<Vec<&Value>, Error> {
+    if let Some(name) = call.args.iter().find_map(|arg| arg.name.as_ref()) {
+        return Err(Error::UnexpectedArgument(name.clone()));
+    }
+    let values = call.positional().collect::<Vec<_>>();
+    if values.len() < required || values.len() > max {
+        return Err(Error::Arity {
+            min: required,
+            max: Some(max),
+            found: values.len(),
+        });
+    }
+    Ok(values)
+}
+
+/// Converts the argument at `index`, which may only be left out when `T`
+/// allows it.
+fn argument<T: FromValue>(values: &[&Value], index: usize) -> Result<T, Error> {
+    match values.get(index) {
+        Some(value) => T::from_value(value).map_err(|e| Error::Argument {
+            position: index + 1,
+            pos: FilePos::default(),
+            error: Box::new(e),
+        }),
+        None => T::missing().ok_or(Error::Arity {
+            min: index + 1,
+            max: None,
+            found: values.len(),
+        }),
+    }
+}
+
+macro_rules! impl_functions {
+    ($max:literal; $($arg:ident $index:literal),*) => {
+        impl<F, R, $($arg),*> ValueFunction<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> Result<R, Error>,
+            R: IntoValue,
+            $($arg: FromValue,)*
+        {
+            fn call(&mut self, call: &Call) -> Result<Value, Error> {
+                let required = required(&[$($arg::missing().is_some()),*]);
+                let _values = positional(call, required, $max)?;
+                self($(argument::<$arg>(&_values, $index)?),*).map(IntoValue::into_value)
+            }
+        }
+
+        impl<F, $($arg),*> RecordFunction<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> Result<Option<Record>, Error>,
+            $($arg: FromValue,)*
+        {
+            fn call(&mut self, call: &Call) -> Result<Option<Record>, Error> {
+                let required = required(&[$($arg::missing().is_some()),*]);
+                let _values = positional(call, required, $max)?;
+                self($(argument::<$arg>(&_values, $index)?),*)
+            }
+        }
+    };
+}
+
+impl_functions!(0;);
+impl_functions!(1; A 0);
+impl_functions!(2; A 0, B 1);
+impl_functions!(3; A 0, B 1, C 2);
+impl_functions!(4; A 0, B 1, C 2, D 3);
+
+/// Value and record functions by name, evaluating documents by calling
+/// them.
+///
+/// ```
+/// use alt::registry::Registry;
+///
+/// let strings = Registry::new().value("upper", |s: String| Ok(s.to_uppercase()));
+/// let registry = Registry::new().namespace("str", strings);
+/// ```
+#[derive(Default)]
+pub struct Registry {
+    values: BTreeMap<String, BoxedValueFunction>,
+    records: BTreeMap<String, BoxedRecordFunction>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `f` as `@name`, replacing any function of that name.
+    #[must_use]
+    pub fn value<Args, F>(self, name: &str, mut f: F) -> Self
+    where
+        F: ValueFunction<Args> + 'static,
+    {
+        self.value_raw(name, move |call: &Call| f.call(call))
+    }
+
+    /// Registers `f` as `#name`, replacing any function of that name.
+    #[must_use]
+    pub fn record<Args, F>(self, name: &str, mut f: F) -> Self
+    where
+        F: RecordFunction<Args> + 'static,
+    {
+        self.record_raw(name, move |call: &Call| f.call(call))
+    }
+
+    /// Registers `f` as `@name`, passing it the call with all its arguments.
+    #[must_use]
+    pub fn value_raw<F>(self, name: &str, f: F) -> Self
+    where
+        F: FnMut(&Call) -> Result<Value, Error> + 'static,
+    {
+        let f = RefCell::new(f);
+        self.value_with(name, move |_, call| (f.borrow_mut())(call))
+    }
+
+    /// Registers `f` as `#name`, passing it the call with all its arguments.
+    #[must_use]
+    pub fn record_raw<F>(self, name: &str, f: F) -> Self
+    where
+        F: FnMut(&Call) -> Result<Option<Record>, Error> + 'static,
+    {
+        let f = RefCell::new(f);
+        self.record_with(name, move |_, call| {
+            (f.borrow_mut())(call).map(|record| record.into_iter().collect())
+        })
+    }
+
+    /// Registers `f` as `@name`, passing it the evaluator the call is
+    /// evaluated with, for functions that evaluate documents of their own.
+    #[must_use]
+    pub fn value_with<F>(mut self, name: &str, f: F) -> Self
+    where
+        F: Fn(&mut dyn eval::Evaluator, &Call) -> Result<Value, Error> + 'static,
+    {
+        self.values.insert(name.to_string(), Rc::new(f));
+        self
+    }
+
+    /// Registers `f` as `#name` like [`Registry::value_with`], adding all
+    /// the records it returns.
+    #[must_use]
+    pub fn record_with<F>(mut self, name: &str, f: F) -> Self
+    where
+        F: Fn(&mut dyn eval::Evaluator, &Call) -> Result<Vec<Record>, Error> + 'static,
+    {
+        self.records.insert(name.to_string(), Rc::new(f));
+        self
+    }
+
+    /// Removes `@name` and `#name`, so that calling them is an error.
+    #[must_use]
+    pub fn without(mut self, name: &str) -> Self {
+        self.values.remove(name);
+        self.records.remove(name);
+        self
+    }
+
+    /// Adds the functions of `other`, which replace those of the same name.
+    #[must_use]
+    pub fn extend(mut self, other: Self) -> Self {
+        self.values.extend(other.values);
+        self.records.extend(other.records);
+        self
+    }
+
+    /// Adds the functions of `other` as `@prefix.name` and `#prefix.name`.
+    #[must_use]
+    pub fn namespace(mut self, prefix: &str, other: Self) -> Self {
+        let name = |name| format!("{prefix}.{name}");
+        self.values
+            .extend(other.values.into_iter().map(|(k, f)| (name(k), f)));
+        self.records
+            .extend(other.records.into_iter().map(|(k, f)| (name(k), f)));
+        self
+    }
+
+    /// The function called by `@name`.
+    pub(crate) fn value_function(&self, name: &str) -> Result<BoxedValueFunction, Error> {
+        match self.values.get(name) {
+            Some(f) => Ok(Rc::clone(f)),
+            None => Err(invalid('@', name, self.values.keys())),
+        }
+    }
+
+    /// The function called by `#name`.
+    pub(crate) fn record_function(&self, name: &str) -> Result<BoxedRecordFunction, Error> {
+        match self.records.get(name) {
+            Some(f) => Ok(Rc::clone(f)),
+            None => Err(invalid('#', name, self.records.keys())),
+        }
+    }
+}
+
+/// The edit distance between `a` and `b`, counting inserted, removed and
+/// replaced characters.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replaced = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The error for calling `sigil` `name`, suggesting the closest of `names`
+/// when it is only a few edits away.
+fn invalid<'a>(sigil: char, name: &str, names: impl Iterator<Item = &'a String>) -> Error {
+    let limit = (name.chars().count() / 3).max(1);
+    let suggestion = names
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, candidate)| format!("{sigil}{candidate}"));
+    Error::InvalidFunction {
+        name: format!("{sigil}{name}"),
+        suggestion,
+    }
+}
+
+impl eval::Evaluator for Registry {
+    fn value_function_eval(&mut self, call: &Call) -> Result<Value, Error> {
+        self.value_function(&call.function)?(self, call)
+    }
+
+    fn record_function_eval(&mut self, call: &Call) -> Result<Vec<Record>, Error> {
+        self.record_function(&call.function)?(self, call)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Evaluator;
+    use crate::{lexer::tokenize, parser::parse};
+
+    fn eval(registry: &mut Registry, source: &str) -> Result<Value, Error> {
+        let tokens = tokenize(source).expect("valid tokens");
+        registry.eval(&parse(&tokens).expect("valid document"))
+    }
+
+    fn object(records: &[(&str, Value)]) -> Value {
+        Value::Object(
+            records
+                .iter()
+                .map(|(id, value)| Record {
+                    id: id.to_string(),
+                    value: value.clone(),
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn typed_functions() -> Result<(), Error> {
+        let mut registry = Registry::new()
+            .value("upper", |s: String| Ok(s.to_uppercase()))
+            .value("add", |a: i64, b: i64| Ok(a + b))
+            .value("half", || Ok(0.5))
+            .record("define", |id: String, value: Value| {
+                Ok(Some(Record {
+                    id,
+                    value,
+                    ..Default::default()
+                }))
+            });
+
+        assert_eq!(
+            eval(
+                &mut registry,
+                "a = @upper \"x\"; b = @add(1, 2); c = @half(); #define(\"d\", true)"
+            )?,
+            object(&[
+                ("a", Value::String("X".to_string())),
+                ("b", Value::Number(3)),
+                ("c", Value::Float(0.5)),
+                ("d", Value::Bool(true)),
+            ])
+        );
+
+        let mut root = |source| eval(&mut registry, source).unwrap_err().root().to_string();
+        assert_eq!(
+            root("a = @upper 1"),
+            "argument 1: expected string, found number"
+        );
+        assert_eq!(root("a = @add(1)"), "expected 2 arguments, found 1");
+        assert_eq!(root("a = @upper(\"x\", y = 1)"), "unexpected argument y");
+
+        Ok(())
+    }
+
+    #[test]
+    fn optional_arguments() -> Result<(), Error> {
+        let mut registry = Registry::new().value("pad", |s: String, width: Option<usize>| {
+            Ok(format!("{s:>0$}", width.unwrap_or(3)))
+        });
+
+        assert_eq!(
+            eval(
+                &mut registry,
+                "a = @pad \"x\"; b = @pad(\"x\", 2); c = @pad(\"x\", null)"
+            )?,
+            object(&[
+                ("a", Value::String("  x".to_string())),
+                ("b", Value::String(" x".to_string())),
+                ("c", Value::String("  x".to_string())),
+            ])
+        );
+
+        let mut root = |source| eval(&mut registry, source).unwrap_err().root().to_string();
+        assert_eq!(root("a = @pad()"), "expected 1 to 2 arguments, found 0");
+        assert_eq!(
+            root("a = @pad(\"x\", 1, 2)"),
+            "expected 1 to 2 arguments, found 3"
+        );
+        assert_eq!(
+            root("a = @pad(\"x\", -1)"),
+            "argument 2: -1 is out of range for a count"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn namespaces() -> Result<(), Error> {
+        let strings = Registry::new().value("upper", |s: String| Ok(s.to_uppercase()));
+        let mut registry = Registry::new()
+            .value_raw("count", |call| Ok(Value::Number(call.args.len() as i64)))
+            .namespace("str", strings);
+
+        assert_eq!(
+            eval(
+                &mut registry,
+                "a = @str.upper \"x\"; b = @count(1, 2, c = 3)"
+            )?,
+            object(&[
+                ("a", Value::String("X".to_string())),
+                ("b", Value::Number(3)),
+            ])
+        );
+        assert!(eval(&mut registry, "a = @upper \"x\"").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn functions_with_the_evaluator() -> Result<(), Error> {
+        // `@twice(f, x)` is `@f(@f(x))`
+        let mut registry = Registry::new()
+            .value("exclaim", |s: String| Ok(format!("{s}!")))
+            .value("upper", |s: String| Ok(s.to_uppercase()))
+            .value_with("twice", |evaluator, call| {
+                let values = positional(call, 2, 2)?;
+                let (function, value) = match values.as_slice() {
+                    [Value::String(function), value] => (function.clone(), (*value).clone()),
+                    values => {
+                        return Err(Error::Type {
+                            expected: "string",
+                            found: values[0].type_name(),
+                        })
+                    }
+                };
+                (0..2).try_fold(value, |value, _| {
+                    evaluator.value_function_eval(&Call {
+                        function: function.clone(),
+                        args: vec![value.into()],
+                        ..Default::default()
+                    })
+                })
+            })
+            .without("upper");
+
+        assert_eq!(
+            eval(&mut registry, "a = @twice(\"exclaim\", \"x\")")?,
+            object(&[("a", Value::String("x!!".to_string()))])
+        );
+        assert_eq!(
+            eval(&mut registry, "a = @twice(\"upper\", \"x\")")
+                .unwrap_err()
+                .root()
+                .to_string(),
+            "invalid function @upper"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut registry = Registry::new()
+            .value("upper", |s: String| Ok(s))
+            .value("lower", |s: String| Ok(s))
+            .record("meta-lang", |_: f64| Ok(None));
+
+        let root = |registry: &mut Registry, source| {
+            eval(registry, source).unwrap_err().root().to_string()
+        };
+        assert_eq!(
+            root(&mut registry, "a = @uper \"x\""),
+            "invalid function @uper, did you mean @upper?"
+        );
+        assert_eq!(
+            root(&mut registry, "#meta_lang 1.0"),
+            "invalid function #meta_lang, did you mean #meta-lang?"
+        );
+        assert_eq!(root(&mut registry, "a = @xyz 1"), "invalid function @xyz");
+        assert_eq!(
+            root(&mut registry, "a = @meta-lang 1.0"),
+            "invalid function @meta-lang"
+        );
+    }
+
+    #[test]
+    fn required_arguments() {
+        assert_eq!(required(&[]), 0);
+        assert_eq!(required(&[false, false, true]), 2);
+        assert_eq!(required(&[true, false]), 2);
+        assert_eq!(required(&[true, true]), 0);
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("same", "same"), 0);
+    }
+}
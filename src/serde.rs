@@ -2,4 +2,6 @@ mod de;
 mod error;
 mod ser;
 
-
+pub use de::{from_str, from_str_with, from_value, Deserializer};
+pub use error::Error;
+pub use ser::{to_string, to_value, Serializer};
@@ -0,0 +1,424 @@
+use super::Error;
+use crate::{
+    ast::{Element, Record, RecordOrCall, Value},
+    eval, goodies, lexer, parser,
+};
+use ::serde::de::{
+    self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, Visitor,
+};
+use ::serde::{forward_to_deserialize_any, Deserialize};
+
+/// Deserializes an evaluated [`Value`] into any `T`.
+pub struct Deserializer<'de> {
+    value: &'de Value,
+}
+
+impl<'de> Deserializer<'de> {
+    pub fn new(value: &'de Value) -> Self {
+        Self { value }
+    }
+}
+
+/// Lexes, parses and evaluates `s` with the [`goodies::Evaluator`], then
+/// deserializes the resulting document.
+pub fn from_str<T: DeserializeOwned>(s: &str) -> Result<T, Error> {
+    from_str_with(s, &mut goodies::Evaluator::default())
+}
+
+/// Like [`from_str`], but evaluates calls with a custom evaluator, whose
+/// [`eval::Evaluator::merge_policy`] merges duplicate keys, as set with
+/// [`goodies::Evaluator::with_merge_policy`].
+pub fn from_str_with<T, E>(s: &str, evaluator: &mut E) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    E: eval::Evaluator,
+{
+    let tokens = lexer::tokenize(s)?;
+    let parsed = parser::parse_with_policy(&tokens, evaluator.merge_policy())?;
+    let value = evaluator.eval(&parsed)?;
+    from_value(&value)
+}
+
+pub fn from_value<'de, T: Deserialize<'de>>(value: &'de Value) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(value))
+}
+
+fn records(records: &[RecordOrCall]) -> Result<Vec<&Record>, Error> {
+    records
+        .iter()
+        .filter_map(|record| match record {
+            RecordOrCall::Record(r) => Some(Ok(r)),
+            RecordOrCall::Call(c) => {
+                Some(Err(Error::UnevaluatedCall("#".to_string() + &c.function)))
+            }
+            RecordOrCall::Comment(_) => None,
+        })
+        .collect()
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::Number(n) => visitor.visit_i64(*n),
+            Value::Float(f) => visitor.visit_f64(*f),
+            Value::String(s) => visitor.visit_borrowed_str(s),
+            Value::Array(values) => visitor.visit_seq(SeqAccess {
+                it: values.iter().enumerate(),
+            }),
+            Value::Object(records) => visitor.visit_map(MapAccess {
+                it: records.iter(),
+                value: None,
+            }),
+            Value::ObjectWithCalls(object) => visitor.visit_map(MapAccess {
+                it: records(object)?.into_iter(),
+                value: None,
+            }),
+            Value::Call(call) => Err(Error::UnevaluatedCall("@".to_string() + &call.function)),
+            Value::Reference(r) => Err(Error::UnevaluatedCall(r.to_string())),
+            Value::Typed(t) => Deserializer::new(&t.value).deserialize_any(visitor),
+            Value::Error(_) => Err(de::Error::invalid_type(unexpected(self.value), &visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        // A `Typed` value keeps the function that produced it, so its kind
+        // selects the variant, e.g. `@std_url "..."` becomes `Url::std_url(..)`.
+        match self.value {
+            Value::String(s) => visitor.visit_enum(EnumAccess {
+                variant: s,
+                value: None,
+            }),
+            Value::Typed(t) => visitor.visit_enum(EnumAccess {
+                variant: &t.kind,
+                value: Some(&t.value),
+            }),
+            Value::Object(records) if records.len() == 1 => visitor.visit_enum(EnumAccess {
+                variant: &records[0].id,
+                value: Some(&records[0].value),
+            }),
+            Value::ObjectWithCalls(object) => match records(object)?[..] {
+                [record] => visitor.visit_enum(EnumAccess {
+                    variant: &record.id,
+                    value: Some(&record.value),
+                }),
+                _ => Err(de::Error::invalid_type(de::Unexpected::Map, &"enum")),
+            },
+            _ => Err(de::Error::invalid_type(unexpected(self.value), &"enum")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+fn unexpected(value: &Value) -> de::Unexpected<'_> {
+    match value {
+        Value::Null => de::Unexpected::Unit,
+        Value::Bool(b) => de::Unexpected::Bool(*b),
+        Value::Number(n) => de::Unexpected::Signed(*n),
+        Value::Float(f) => de::Unexpected::Float(*f),
+        Value::String(s) => de::Unexpected::Str(s),
+        Value::Array(_) => de::Unexpected::Seq,
+        Value::Object(_) | Value::ObjectWithCalls(_) => de::Unexpected::Map,
+        Value::Call(_) => de::Unexpected::Other("call"),
+        Value::Reference(_) => de::Unexpected::Other("reference"),
+        Value::Typed(t) => unexpected(&t.value),
+        Value::Error(_) => de::Unexpected::Other("value that failed to parse"),
+    }
+}
+
+/// Errors in an element are given at its index.
+struct SeqAccess<I> {
+    it: I,
+}
+
+impl<'de, I> de::SeqAccess<'de> for SeqAccess<I>
+where
+    I: Iterator<Item = (usize, &'de Element)>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.it
+            .next()
+            .map(|(i, element)| {
+                seed.deserialize(Deserializer::new(&element.value))
+                    .map_err(|e| e.in_field(&i.to_string(), element.pos))
+            })
+            .transpose()
+    }
+}
+
+/// Errors in a value are given at the record's key.
+struct MapAccess<'de, I> {
+    it: I,
+    value: Option<&'de Record>,
+}
+
+impl<'de, I> de::MapAccess<'de> for MapAccess<'de, I>
+where
+    I: Iterator<Item = &'de Record>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.it.next() {
+            Some(record) => {
+                self.value = Some(record);
+                seed.deserialize(BorrowedStrDeserializer::new(&record.id))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(record) => seed
+                .deserialize(Deserializer::new(&record.value))
+                .map_err(|e| e.in_field(&record.id, record.pos)),
+            None => Err(de::Error::custom("value requested before key")),
+        }
+    }
+}
+
+struct EnumAccess<'de> {
+    variant: &'de str,
+    value: Option<&'de Value>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = Error;
+    type Variant = VariantAccess<'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess<'de> {
+    value: Option<&'de Value>,
+}
+
+impl<'de> VariantAccess<'de> {
+    fn value(self, expected: &str) -> Result<&'de Value, Error> {
+        self.value
+            .ok_or_else(|| de::Error::invalid_type(de::Unexpected::UnitVariant, &expected))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.value {
+            None => Ok(()),
+            Some(value) => Err(de::Error::invalid_type(unexpected(value), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(Deserializer::new(self.value("newtype variant")?))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_seq(Deserializer::new(self.value("tuple variant")?), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_map(Deserializer::new(self.value("struct variant")?), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::FilePos;
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        host: String,
+        port: u16,
+        ratio: f64,
+        tags: Vec<String>,
+        limits: HashMap<String, i32>,
+        enabled: bool,
+        backup: Option<Box<Server>>,
+        fallback: Option<String>,
+    }
+
+    #[test]
+    fn deserialize_struct() -> Result<(), Error> {
+        let server: Server = from_str(
+            "host = \"localhost\"
+            port = 8080
+            ratio = 0.5
+            tags = [\"a\" \"b\"]
+            limits = {cpu = 2; memory = 512}
+            enabled = true
+            fallback = null",
+        )?;
+
+        assert_eq!(
+            server,
+            Server {
+                host: "localhost".to_string(),
+                port: 8080,
+                ratio: 0.5,
+                tags: vec!["a".to_string(), "b".to_string()],
+                limits: HashMap::from([("cpu".to_string(), 2), ("memory".to_string(), 512)]),
+                enabled: true,
+                backup: None,
+                fallback: None,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[allow(non_camel_case_types)]
+    enum Endpoint {
+        std_url(String),
+        Socket { path: String },
+        Disabled,
+    }
+
+    #[test]
+    fn typed_values_select_variants() -> Result<(), Error> {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            a: Endpoint,
+            b: Endpoint,
+            c: Endpoint,
+        }
+
+        let config: Config = from_str(
+            "a = @std_url \"localhost\"
+            b = {Socket = {path = \"/tmp/sock\"}}
+            c = \"Disabled\"",
+        )?;
+
+        assert_eq!(
+            config,
+            Config {
+                a: Endpoint::std_url("localhost".to_string()),
+                b: Endpoint::Socket {
+                    path: "/tmp/sock".to_string()
+                },
+                c: Endpoint::Disabled,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn out_of_range_number() {
+        #[derive(Debug, Deserialize)]
+        struct Small {
+            #[allow(dead_code)]
+            x: u8,
+        }
+
+        let err = from_str::<Small>("a = 1\nx = 300").unwrap_err();
+        assert!(matches!(&err, Error::Field { key, .. } if key == "x"));
+        assert_eq!(err.pos(), Some(FilePos { start: 6, end: 7 }));
+        assert_eq!(
+            err.to_string(),
+            "x: invalid value: integer `300`, expected u8"
+        );
+    }
+
+    #[test]
+    fn errors_carry_key_path() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Port {
+            port: u16,
+        }
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[allow(dead_code)]
+            servers: Vec<Port>,
+        }
+
+        let err = from_str::<Config>(
+            "servers = [
+                {port = 80}
+                {port = \"80\"}
+            ]",
+        )
+        .unwrap_err();
+        assert!(matches!(&err, Error::Field { key, .. } if key == "servers.1.port"));
+        assert_eq!(err.pos().map(|p| p.start), Some(57));
+    }
+
+    #[test]
+    fn duplicate_keys_follow_the_merge_policy() -> Result<(), Error> {
+        use crate::merge::MergePolicy;
+
+        let source = "a = 1\na = 2";
+        assert!(from_str::<HashMap<String, i32>>(source).is_err());
+        let mut evaluator = goodies::Evaluator::default().with_merge_policy(MergePolicy::LastWins);
+        let map: HashMap<String, i32> = from_str_with(source, &mut evaluator)?;
+        assert_eq!(map, HashMap::from([("a".to_string(), 2)]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors_carry_position() {
+        let err = from_str::<HashMap<String, i32>>("x = 1\ny 2").unwrap_err();
+        assert!(matches!(err, Error::Parse(_)));
+        assert_eq!(err.pos().map(|p| p.start), Some(8));
+    }
+}
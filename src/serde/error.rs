@@ -0,0 +1,116 @@
+use crate::{
+    eval,
+    lexer::{self, FilePos},
+    parser, printer,
+};
+use core::fmt;
+use std::error::Error as StdError;
+
+#[derive(Debug)]
+pub enum Error {
+    Message(String),
+    Parse(parser::Error),
+    Eval(eval::Error),
+    Print(printer::Error),
+
+    UnevaluatedCall(String),
+    UnsupportedType(&'static str),
+    NumberOutOfRange(String),
+    ExpectedDocument,
+    /// An error deserializing the value at the dotted `key`, array elements
+    /// counting as keys by their index. `pos` is where the innermost record
+    /// or element with a known position was written.
+    Field {
+        key: String,
+        pos: FilePos,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The position in the source document that caused the error, if known.
+    pub fn pos(&self) -> Option<FilePos> {
+        match self {
+            Self::Parse(e) => Some(e.pos),
+            Self::Eval(e) => e.pos(),
+            Self::Field { pos, .. } => Some(*pos).filter(|pos| *pos != FilePos::default()),
+            _ => None,
+        }
+    }
+
+    /// Adds `key`, written at `pos`, in front of the key the error is at.
+    pub(super) fn in_field(self, key: &str, pos: FilePos) -> Self {
+        match self {
+            Self::Field {
+                key: inner,
+                pos: at,
+                error,
+            } => Self::Field {
+                key: format!("{key}.{inner}"),
+                pos: if at == FilePos::default() { pos } else { at },
+                error,
+            },
+            error => Self::Field {
+                key: key.to_string(),
+                pos,
+                error: Box::new(error),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Message(msg) => write!(f, "{msg}"),
+            Self::Parse(e) => write!(f, "parsing error: {e}"),
+            Self::Eval(e) => write!(f, "eval error: {e}"),
+            Self::Print(e) => write!(f, "printing error: {e}"),
+            Self::UnevaluatedCall(function) => {
+                write!(f, "call to {function} was not evaluated")
+            }
+            Self::UnsupportedType(kind) => write!(f, "{kind} cannot be represented in alt"),
+            Self::NumberOutOfRange(n) => write!(f, "number {n} is out of range"),
+            Self::ExpectedDocument => write!(f, "expected a struct or map at the top level"),
+            Self::Field { key, error, .. } => write!(f, "{key}: {error}"),
+        }
+    }
+}
+
+impl StdError for Error {}
+
+impl ::serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::Message(msg.to_string())
+    }
+}
+
+impl ::serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::Message(msg.to_string())
+    }
+}
+
+impl From<parser::Error> for Error {
+    fn from(value: parser::Error) -> Self {
+        Self::Parse(value)
+    }
+}
+
+impl From<lexer::Error> for Error {
+    fn from(value: lexer::Error) -> Self {
+        Self::Parse(value.into())
+    }
+}
+
+impl From<eval::Error> for Error {
+    fn from(value: eval::Error) -> Self {
+        Self::Eval(value)
+    }
+}
+
+impl From<printer::Error> for Error {
+    fn from(value: printer::Error) -> Self {
+        Self::Print(value)
+    }
+}
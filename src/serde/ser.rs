@@ -0,0 +1,462 @@
+use super::Error;
+use crate::{
+    ast::{Record, Value},
+    printer,
+};
+use ::serde::ser::{self, Serialize};
+
+/// Serializes any `T` into a [`Value`].
+pub struct Serializer;
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(Serializer)
+}
+
+/// Serializes `value` into alt source. The top level has to be a struct or
+/// a map, since an alt document is a list of records.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    match to_value(value)? {
+        value @ Value::Object(_) => Ok(printer::print(&value)?),
+        _ => Err(Error::ExpectedDocument),
+    }
+}
+
+fn number<N: TryInto<i64> + ToString + Copy>(n: N) -> Result<Value, Error> {
+    n.try_into()
+        .map(Value::Number)
+        .map_err(|_| Error::NumberOutOfRange(n.to_string()))
+}
+
+fn tagged(variant: &str, value: Value) -> Value {
+    Value::Object(vec![Record {
+        id: variant.to_string(),
+        value,
+        ..Default::default()
+    }])
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeObject;
+    type SerializeStruct = SerializeObject;
+    type SerializeStructVariant = SerializeObject;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        number(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        number(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::Number(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        number(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        number(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        number(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        number(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::array(v.iter().map(|b| Value::Number((*b).into()))))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        Ok(tagged(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray {
+            variant: None,
+            values: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray {
+            variant: Some(variant),
+            values: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeObject, Error> {
+        Ok(SerializeObject {
+            variant: None,
+            records: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeObject, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeObject, Error> {
+        Ok(SerializeObject {
+            variant: Some(variant),
+            records: Vec::with_capacity(len),
+            key: None,
+        })
+    }
+}
+
+pub struct SerializeArray {
+    variant: Option<&'static str>,
+    values: Vec<Value>,
+}
+
+impl SerializeArray {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.values.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        let array = Value::array(self.values);
+        match self.variant {
+            Some(variant) => tagged(variant, array),
+            None => array,
+        }
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+pub struct SerializeObject {
+    variant: Option<&'static str>,
+    records: Vec<Record>,
+    key: Option<String>,
+}
+
+impl SerializeObject {
+    fn push<T: Serialize + ?Sized>(&mut self, id: String, value: &T) -> Result<(), Error> {
+        self.records.push(Record {
+            id,
+            value: value.serialize(Serializer)?,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        let object = Value::Object(self.records);
+        match self.variant {
+            Some(variant) => tagged(variant, object),
+            None => object,
+        }
+    }
+}
+
+impl ser::SerializeMap for SerializeObject {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(Serializer)? {
+            Value::String(s) => {
+                self.key = Some(s);
+                Ok(())
+            }
+            _ => Err(Error::UnsupportedType("non-string key")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| <Error as ser::Error>::custom("value serialized before key"))?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStruct for SerializeObject {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeObject {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde::from_str;
+    use crate::{lexer, parser};
+    use ::serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Mode {
+        Fast,
+        Limited(i32),
+        Custom { name: String },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        ratio: f32,
+        ports: Vec<u16>,
+        modes: Vec<Mode>,
+        extra: BTreeMap<String, String>,
+        verbose: bool,
+        parent: Option<String>,
+    }
+
+    #[test]
+    fn serialize_document() -> Result<(), Error> {
+        let config = Config {
+            name: "server".to_string(),
+            ratio: 2.0,
+            ports: vec![80, 443],
+            modes: vec![
+                Mode::Fast,
+                Mode::Limited(3),
+                Mode::Custom {
+                    name: "x".to_string(),
+                },
+            ],
+            extra: BTreeMap::new(),
+            verbose: false,
+            parent: None,
+        };
+
+        let s = to_string(&config)?;
+        assert_eq!(
+            s,
+            "name = \"server\"
+ratio = 2.0
+ports = [80 443]
+modes = [\"Fast\" {
+    Limited = 3
+} {
+    Custom = {
+        name = \"x\"
+    }
+}]
+extra = {}
+verbose = false
+parent = null
+"
+        );
+        assert_eq!(from_str::<Config>(&s)?, config);
+
+        Ok(())
+    }
+
+    #[test]
+    fn top_level_must_be_a_document() {
+        assert!(matches!(to_string(&[1, 2]), Err(Error::ExpectedDocument)));
+    }
+
+    #[test]
+    fn keys_must_be_identifiers() {
+        let map = BTreeMap::from([("not valid", 1)]);
+        assert!(matches!(
+            to_string(&map),
+            Err(Error::Print(printer::Error::InvalidKey(_)))
+        ));
+    }
+
+    #[test]
+    fn unevaluated_calls_are_errors() -> Result<(), Error> {
+        let parsed = parser::parse(&lexer::tokenize("x = [@f 1]")?)?;
+        let err = to_value(&parsed).unwrap_err();
+        assert_eq!(err.to_string(), "call to @f was not evaluated");
+        Ok(())
+    }
+
+    #[test]
+    fn numbers_out_of_range() {
+        assert!(matches!(
+            to_value(&u64::MAX),
+            Err(Error::NumberOutOfRange(_))
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+//! Runs the example documents in `tests/conformance` through the lexer,
+//! parser and evaluator and compares what comes out with what the examples
+//! of `grammar.ebnf` expect. The grammar itself is not checked here.
+//!
+//! Every `NAME.alt` comes with either `NAME.json`, the JSON the document
+//! evaluates to, or `NAME.err`, one `LINE:COLUMN: MESSAGE` line for each
+//! error it is rejected with. Positions inside messages are given as
+//! `LINE:COLUMN` as well, so the expectations read like the documents.
+
+use alt::diagnostics::{line_col, parse_message};
+use alt::eval::Evaluator as _;
+use alt::{goodies, lexer, parser};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+enum Outcome {
+    Json(serde_json::Value),
+    Errors(String),
+}
+
+fn error_line(source: &str, start: usize, message: impl std::fmt::Display) -> String {
+    format!("{}: {message}\n", line_col(source, start))
+}
+
+fn run(source: &str) -> Outcome {
+    let tokens = match lexer::tokenize(source) {
+        Ok(tokens) => tokens,
+        Err(e) => return Outcome::Errors(error_line(source, e.pos.start, e.error)),
+    };
+    let mut evaluator = goodies::Evaluator::default();
+    let (document, errors) = parser::parse_recovering(&tokens, evaluator.merge_policy());
+    if !errors.is_empty() {
+        let lines = errors
+            .iter()
+            .map(|e| error_line(source, e.pos.start, parse_message(&e.error, source)))
+            .collect();
+        return Outcome::Errors(lines);
+    }
+
+    match evaluator.eval(&document) {
+        Ok(value) => Outcome::Json(serde_json::to_value(&value).expect("evaluated value")),
+        Err(e) => {
+            let start = e.pos().map_or(0, |pos| pos.start);
+            Outcome::Errors(error_line(source, start, e))
+        }
+    }
+}
+
+fn check(path: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let json = path.with_extension("json");
+    let err = path.with_extension("err");
+
+    match (run(&source), json.exists()) {
+        (Outcome::Json(found), true) => {
+            let text = fs::read_to_string(&json).map_err(|e| e.to_string())?;
+            let expected: serde_json::Value =
+                serde_json::from_str(&text).map_err(|e| format!("{}: {e}", json.display()))?;
+            if found != expected {
+                return Err(format!("expected {expected}\n  found {found}"));
+            }
+        }
+        (Outcome::Errors(found), false) if err.exists() => {
+            let expected = fs::read_to_string(&err).map_err(|e| e.to_string())?;
+            if found != expected {
+                return Err(format!("expected errors:\n{expected}  found:\n{found}"));
+            }
+        }
+        (Outcome::Json(found), false) => {
+            return Err(format!("accepted as {found}, expected errors"));
+        }
+        (Outcome::Errors(found), true) => {
+            return Err(format!("rejected with:\n{found}"));
+        }
+        (Outcome::Errors(_), false) => {
+            return Err("no .json or .err expectation".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn conformance() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut cases = fs::read_dir(&dir)
+        .expect("conformance directory")
+        .map(|entry| entry.expect("directory entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "alt"))
+        .collect::<Vec<PathBuf>>();
+    cases.sort();
+    assert!(!cases.is_empty(), "no cases in {}", dir.display());
+
+    let failures = cases
+        .iter()
+        .filter_map(|path| {
+            check(path).err().map(|e| {
+                format!(
+                    "{}: {e}",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                )
+            })
+        })
+        .collect::<Vec<String>>();
+    assert!(
+        failures.is_empty(),
+        "{} of {} conformance cases failed:\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n\n")
+    );
+}